@@ -0,0 +1,339 @@
+use serenity::all::{
+    ActionRowComponent, ButtonStyle, ComponentInteraction, CreateActionRow, CreateButton,
+    CreateInputText, CreateInteractionResponse, CreateInteractionResponseMessage, CreateModal,
+    InputTextStyle, ModalInteraction, Permissions, User,
+};
+use serenity::builder::{CreateEmbed, CreateMessage, EditMember};
+use serenity::model::Color;
+use serenity::prelude::Context;
+
+use crate::{
+    config::Punishment,
+    engine::ModerationEngine,
+    escalation::unix_now,
+    store::{Appeal, EscalationEntry},
+};
+
+static HISTORY_LIMIT: usize = 5;
+static MAX_STATEMENT_CHARS: u16 = 1000;
+
+pub fn appeal_button(decision_id: i64) -> CreateActionRow {
+    CreateActionRow::Buttons(vec![CreateButton::new(format!("appeal:{}", decision_id))
+        .label("Appeal")
+        .style(ButtonStyle::Primary)])
+}
+
+fn decision_buttons(decision_id: i64) -> CreateActionRow {
+    CreateActionRow::Buttons(vec![
+        CreateButton::new(format!("appeal:{}:accept", decision_id))
+            .label("Accept")
+            .style(ButtonStyle::Success),
+        CreateButton::new(format!("appeal:{}:reject", decision_id))
+            .label("Reject")
+            .style(ButtonStyle::Danger),
+    ])
+}
+
+fn parse_custom_id(custom_id: &str) -> Option<(i64, Option<&str>)> {
+    let mut parts = custom_id.strip_prefix("appeal:")?.splitn(2, ':');
+    let decision_id = parts.next()?.parse().ok()?;
+    Some((decision_id, parts.next()))
+}
+
+fn generate_appeal_embed(engine: &ModerationEngine, user: &User, appeal: &Appeal) -> CreateMessage {
+    let current = engine.config.current();
+    let config = current.for_guild(Some(appeal.guild_id));
+
+    let mut history = match engine.store.strikes(
+        appeal.guild_id,
+        appeal.user_id,
+        config.escalation.half_life_hours,
+    ) {
+        Ok(strikes) => format!("{:.1} strikes", strikes),
+        Err(e) => format!("unavailable ({})", e),
+    };
+    if let Ok(decisions) = engine
+        .store
+        .history(appeal.guild_id, appeal.user_id, HISTORY_LIMIT)
+    {
+        for d in decisions {
+            history.push_str(&format!(
+                "\n<t:{}:R> {} ({}): {}",
+                d.created_at, d.action, d.score, d.reason
+            ));
+        }
+    }
+
+    let embed = CreateEmbed::default()
+        .title(format!("{} appealed a {}", user.tag(), appeal.action))
+        .color(Color::BLUE)
+        .description(format!(
+            ">>> ***Message: *** :warning: ||{}||\n***Channel: ***<#{}>\n***Score: ***{}\n***AI Thoughts: ***{}\n***Appeal: ***{}\n***History: ***{}",
+            appeal.content.as_deref().unwrap_or("(not kept)"),
+            appeal.channel_id,
+            appeal.score,
+            appeal.reason,
+            appeal.statement,
+            history
+        ));
+    CreateMessage::new()
+        .embed(embed)
+        .components(vec![decision_buttons(appeal.decision_id)])
+}
+
+fn reply(content: impl Into<String>) -> CreateInteractionResponse {
+    CreateInteractionResponse::Message(
+        CreateInteractionResponseMessage::new()
+            .content(content)
+            .ephemeral(true),
+    )
+}
+
+fn open_modal(decision_id: i64) -> CreateInteractionResponse {
+    CreateInteractionResponse::Modal(
+        CreateModal::new(format!("appeal:{}", decision_id), "Appeal this warning").components(
+            vec![CreateActionRow::InputText(
+                CreateInputText::new(
+                    InputTextStyle::Paragraph,
+                    "Why should the warning be reversed?",
+                    "statement",
+                )
+                .max_length(MAX_STATEMENT_CHARS),
+            )],
+        ),
+    )
+}
+
+// Lifts a timeout that is still running. Kicks and bans are left to the
+// moderators, as are timeouts that fail to lift. Returns whether anything was
+// lifted.
+async fn reverse_escalation(
+    ctx: &Context,
+    appeal: &Appeal,
+    escalation: &EscalationEntry,
+) -> Result<bool, String> {
+    if escalation.action != Punishment::Timeout.to_string() {
+        return Err("kicks and bans have to be reversed by hand".to_string());
+    }
+    if escalation.created_at as u64 + escalation.duration_minutes * 60 <= unix_now() {
+        return Ok(false);
+    }
+    appeal
+        .guild_id
+        .edit_member(
+            ctx,
+            appeal.user_id,
+            EditMember::new()
+                .enable_communication()
+                .audit_log_reason(&format!("appeal {} accepted", appeal.decision_id)),
+        )
+        .await
+        .map(|_| true)
+        .map_err(|e| e.to_string())
+}
+
+async fn decide(
+    ctx: &Context,
+    interaction: &ComponentInteraction,
+    engine: &ModerationEngine,
+    decision_id: i64,
+    accepted: bool,
+) -> CreateInteractionResponse {
+    let allowed = interaction
+        .member
+        .as_ref()
+        .and_then(|m| m.permissions)
+        .is_some_and(|p| p.contains(Permissions::MODERATE_MEMBERS));
+    if !allowed {
+        return reply(format!(
+            "You need the {} permission to decide appeals.",
+            Permissions::MODERATE_MEMBERS
+        ));
+    }
+
+    let outcome = if accepted { "accepted" } else { "rejected" };
+    let appeal = match engine
+        .store
+        .decide_appeal(decision_id, outcome, interaction.user.id)
+    {
+        Ok(Some(appeal)) => appeal,
+        Ok(None) => return reply("This appeal was already decided."),
+        Err(e) => {
+            log::error!("Failed to decide appeal {}: {}", decision_id, e);
+            return reply(format!("Could not decide the appeal: {}", e));
+        }
+    };
+    log::info!(
+        "Appeal {} in guild {} {} by {}",
+        decision_id,
+        appeal.guild_id,
+        outcome,
+        interaction.user.id
+    );
+
+    let mut notes = vec![];
+    let mut notice = if accepted {
+        "Your appeal was accepted and the warning has been withdrawn.".to_string()
+    } else {
+        "Your appeal was reviewed and the warning stands.".to_string()
+    };
+    if accepted {
+        let current = engine.config.current();
+        let config = current.for_guild(Some(appeal.guild_id));
+        if appeal.strikes > 0.0 {
+            if let Err(e) = engine.store.add_strikes(
+                appeal.guild_id,
+                appeal.user_id,
+                -appeal.strikes,
+                config.escalation.half_life_hours,
+            ) {
+                log::error!("Failed to remove strikes for appeal {}: {}", decision_id, e);
+                notes.push(format!(
+                    "The {:.1} strike(s) could not be removed: {}",
+                    appeal.strikes, e
+                ));
+            }
+        }
+        match engine.store.reverse_escalations(decision_id) {
+            Ok(escalations) => {
+                for e in escalations {
+                    match reverse_escalation(ctx, &appeal, &e).await {
+                        Ok(true) => notice.push_str(" Your timeout has been lifted."),
+                        Ok(false) => {}
+                        Err(reason) => {
+                            notice.push_str(&format!(
+                                " The {} it led to stands until a moderator reverses it.",
+                                e.action
+                            ));
+                            notes.push(format!(
+                                "The {} this led to was not reversed: {}",
+                                e.action, reason
+                            ));
+                        }
+                    }
+                }
+            }
+            Err(e) => {
+                log::error!(
+                    "Failed to reverse escalations for appeal {}: {}",
+                    decision_id,
+                    e
+                );
+                notes.push(format!("Escalations could not be looked up: {}", e));
+            }
+        }
+    }
+
+    if let Err(e) = appeal
+        .user_id
+        .direct_message(
+            ctx,
+            CreateMessage::new().content(format!(
+                "{}\nAppealed message: {}",
+                notice,
+                appeal.content.as_deref().unwrap_or("(not kept)")
+            )),
+        )
+        .await
+    {
+        log::warn!(
+            "Failed to tell {} about their appeal: {}",
+            appeal.user_id,
+            e
+        );
+    }
+
+    CreateInteractionResponse::UpdateMessage(
+        CreateInteractionResponseMessage::new()
+            .content(format!(
+                "{} by <@{}>{}",
+                if accepted { "Accepted" } else { "Rejected" },
+                interaction.user.id,
+                notes
+                    .iter()
+                    .map(|n| format!("\n:warning: {}", n))
+                    .collect::<String>()
+            ))
+            .components(vec![]),
+    )
+}
+
+pub async fn handle_button(
+    ctx: &Context,
+    interaction: &ComponentInteraction,
+    engine: &ModerationEngine,
+) {
+    let Some((decision_id, kind)) = parse_custom_id(&interaction.data.custom_id) else {
+        return;
+    };
+
+    let response = match kind {
+        None => open_modal(decision_id),
+        Some("accept") => decide(ctx, interaction, engine, decision_id, true).await,
+        Some("reject") => decide(ctx, interaction, engine, decision_id, false).await,
+        Some(_) => return,
+    };
+    if let Err(e) = interaction.create_response(ctx, response).await {
+        log::error!("Failed to respond to appeal {}: {}", decision_id, e);
+    }
+}
+
+pub async fn handle_modal(
+    ctx: &Context,
+    interaction: &ModalInteraction,
+    engine: &ModerationEngine,
+) {
+    let Some((decision_id, None)) = parse_custom_id(&interaction.data.custom_id) else {
+        return;
+    };
+    let statement = interaction
+        .data
+        .components
+        .iter()
+        .flat_map(|row| &row.components)
+        .find_map(|c| match c {
+            ActionRowComponent::InputText(input) if input.custom_id == "statement" => {
+                input.value.as_deref()
+            }
+            _ => None,
+        })
+        .unwrap_or_default()
+        .trim();
+
+    let response = if statement.is_empty() {
+        reply("Please explain why the warning should be reversed.")
+    } else {
+        match engine
+            .store
+            .submit_appeal(decision_id, interaction.user.id, statement)
+        {
+            Ok(Some(appeal)) => {
+                let current = engine.config.current();
+                let channel = current.for_guild(Some(appeal.guild_id)).appeals_channel;
+                match channel {
+                    Some(channel) => {
+                        let posted = channel
+                            .send_message(
+                                ctx,
+                                generate_appeal_embed(engine, &interaction.user, &appeal),
+                            )
+                            .await;
+                        if let Err(e) = posted {
+                            log::error!("Failed to post appeal {}: {}", decision_id, e);
+                        }
+                        reply("Your appeal has been sent to the moderators.")
+                    }
+                    None => reply("This server no longer accepts appeals."),
+                }
+            }
+            Ok(None) => reply("You have already appealed this warning."),
+            Err(e) => {
+                log::error!("Failed to submit appeal {}: {}", decision_id, e);
+                reply("Your appeal could not be submitted, please try again later.")
+            }
+        }
+    };
+    if let Err(e) = interaction.create_response(ctx, response).await {
+        log::error!("Failed to respond to appeal {}: {}", decision_id, e);
+    }
+}
@@ -0,0 +1,144 @@
+use std::io::Cursor;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use image::{
+    codecs::gif::GifDecoder, AnimationDecoder, DynamicImage, ImageDecoder, ImageOutputFormat,
+};
+
+use crate::{
+    config::AttachmentConfig, constants::MAX_GIF_PIXELS, engine::AttachmentInput, provider::Part,
+};
+
+async fn download(
+    http: &reqwest::Client,
+    config: &AttachmentConfig,
+    attachment: &AttachmentInput,
+) -> Result<Part, String> {
+    let mime_type = attachment
+        .content_type
+        .as_deref()
+        .map(|t| t.split(';').next().unwrap_or(t).trim().to_lowercase())
+        .ok_or("no content type")?;
+    if !config.allowed_types.contains(&mime_type) {
+        return Err(format!("type {} is not allowed", mime_type));
+    }
+    if attachment.size > config.max_bytes {
+        return Err(format!(
+            "{} bytes is above the {} byte limit",
+            attachment.size, config.max_bytes
+        ));
+    }
+
+    let mut res = http
+        .get(&attachment.url)
+        .send()
+        .await
+        .and_then(|res| res.error_for_status())
+        .map_err(|e| e.to_string())?;
+    let too_large = |size: u64| {
+        format!(
+            "downloaded {} bytes, above the {} byte limit",
+            size, config.max_bytes
+        )
+    };
+    if let Some(length) = res.content_length().filter(|l| *l > config.max_bytes) {
+        return Err(too_large(length));
+    }
+    // Read in chunks so a body larger than it claimed is never buffered whole.
+    let mut bytes = vec![];
+    while let Some(chunk) = res.chunk().await.map_err(|e| e.to_string())? {
+        if (bytes.len() + chunk.len()) as u64 > config.max_bytes {
+            return Err(too_large((bytes.len() + chunk.len()) as u64));
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+
+    let (mime_type, data) = if mime_type == "image/gif" {
+        // Decoding is CPU-bound, keep it off the async workers.
+        let png = tokio::task::spawn_blocking(move || first_frame(&bytes))
+            .await
+            .map_err(|e| e.to_string())??;
+        ("image/png".to_string(), png)
+    } else {
+        (mime_type, bytes)
+    };
+
+    Ok(Part::Image {
+        mime_type,
+        data: STANDARD.encode(data),
+    })
+}
+
+fn first_frame(gif: &[u8]) -> Result<Vec<u8>, String> {
+    let decoder = GifDecoder::new(Cursor::new(gif)).map_err(|e| e.to_string())?;
+    let (width, height) = decoder.dimensions();
+    if width as u64 * height as u64 > MAX_GIF_PIXELS {
+        return Err(format!(
+            "{}x{} GIF is above the {} pixel limit",
+            width, height, MAX_GIF_PIXELS
+        ));
+    }
+
+    let frame = decoder
+        .into_frames()
+        .next()
+        .ok_or("GIF has no frames")?
+        .map_err(|e| e.to_string())?;
+
+    let mut png = Cursor::new(vec![]);
+    DynamicImage::ImageRgba8(frame.into_buffer())
+        .write_to(&mut png, ImageOutputFormat::Png)
+        .map_err(|e| e.to_string())?;
+    Ok(png.into_inner())
+}
+
+pub async fn attachment_parts(
+    http: &reqwest::Client,
+    config: &AttachmentConfig,
+    attachments: &[AttachmentInput],
+) -> Vec<Part> {
+    if !config.enabled {
+        return vec![];
+    }
+
+    let mut parts = vec![];
+    for attachment in attachments.iter().take(config.max_count) {
+        match download(http, config, attachment).await {
+            Ok(part) => parts.push(part),
+            Err(e) => log::debug!("Skipping attachment {}: {}", attachment.filename, e),
+        }
+    }
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use wiremock::{matchers::method, Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn sizes_are_checked_against_the_body() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_body_bytes(vec![0u8; 2048]))
+            .mount(&server)
+            .await;
+        let config = AttachmentConfig {
+            max_bytes: 1024,
+            ..AttachmentConfig::default()
+        };
+        let attachment = AttachmentInput {
+            filename: "small.png".to_string(),
+            url: server.uri(),
+            size: 16,
+            content_type: Some("image/png".to_string()),
+        };
+
+        let result = download(&reqwest::Client::new(), &config, &attachment).await;
+        assert_eq!(
+            result.err().as_deref(),
+            Some("downloaded 2048 bytes, above the 1024 byte limit")
+        );
+    }
+}
@@ -0,0 +1,330 @@
+use std::sync::atomic::Ordering;
+
+use serenity::all::{
+    Channel, ChannelId, CommandInteraction, CommandOptionType, GuildId, Permissions,
+    ResolvedOption, ResolvedValue, User,
+};
+use serenity::builder::{CreateCommand, CreateCommandOption, EditInteractionResponse};
+use serenity::prelude::Context;
+
+use crate::{
+    config::EnsembleStrategy, constants::CHANNEL_SETTINGS, engine::ModerationEngine,
+    metrics::METRICS,
+};
+
+static HISTORY_LIMIT: usize = 10;
+static MAX_RESPONSE_CHARS: usize = 1900;
+
+pub fn definitions() -> Vec<CreateCommand> {
+    vec![
+        CreateCommand::new("modconfig")
+            .description("Show or change the moderation settings for this server")
+            .default_member_permissions(Permissions::MANAGE_GUILD)
+            .dm_permission(false)
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::SubCommand,
+                "show",
+                "Show the settings in effect for this server",
+            ))
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::SubCommand,
+                    "set",
+                    "Override a setting for this server",
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "key",
+                        "Setting to change, e.g. delete_threshold or escalation.enabled",
+                    )
+                    .required(true),
+                )
+                .add_sub_option(
+                    CreateCommandOption::new(
+                        CommandOptionType::String,
+                        "value",
+                        "New value, written as TOML",
+                    )
+                    .required(true),
+                ),
+            ),
+        CreateCommand::new("modstatus")
+            .description("Show the moderator's status and recent activity")
+            .default_member_permissions(Permissions::MODERATE_MEMBERS)
+            .dm_permission(false),
+        CreateCommand::new("modhistory")
+            .description("Show a user's recent violations, strikes and escalations")
+            .default_member_permissions(Permissions::MODERATE_MEMBERS)
+            .dm_permission(false)
+            .add_option(
+                CreateCommandOption::new(CommandOptionType::User, "user", "User to look up")
+                    .required(true),
+            ),
+        CreateCommand::new("modtest")
+            .description("Score a text with this server's settings without acting on it")
+            .default_member_permissions(Permissions::MODERATE_MEMBERS)
+            .dm_permission(false)
+            .add_option(
+                CreateCommandOption::new(CommandOptionType::String, "text", "Text to score")
+                    .required(true),
+            ),
+    ]
+}
+
+fn string_option<'a>(options: &[ResolvedOption<'a>], name: &str) -> Option<&'a str> {
+    options.iter().find_map(|o| match o.value {
+        ResolvedValue::String(s) if o.name == name => Some(s),
+        _ => None,
+    })
+}
+
+fn user_option<'a>(options: &[ResolvedOption<'a>], name: &str) -> Option<&'a User> {
+    options.iter().find_map(|o| match o.value {
+        ResolvedValue::User(user, _) if o.name == name => Some(user),
+        _ => None,
+    })
+}
+
+fn truncate(text: String) -> String {
+    if text.chars().count() <= MAX_RESPONSE_CHARS {
+        return text;
+    }
+    let mut text = text.chars().take(MAX_RESPONSE_CHARS).collect::<String>();
+    text.push_str("\n…");
+    text
+}
+
+fn config_show(engine: &ModerationEngine, guild_id: GuildId) -> String {
+    let current = engine.config.current();
+    let config = current.for_guild(Some(guild_id));
+    match toml::to_string(config) {
+        Ok(text) => format!("```toml\n{}\n```", text),
+        Err(e) => format!("Could not show the config: {}", e),
+    }
+}
+
+// Log channels must belong to the server setting them, or its moderation
+// reports would end up in someone else's server.
+async fn check_channel(ctx: &Context, guild_id: GuildId, value: &str) -> Result<(), String> {
+    let id = value
+        .trim()
+        .trim_matches('"')
+        .parse::<u64>()
+        .ok()
+        .filter(|&id| id != 0)
+        .ok_or_else(|| format!("{} is not a channel id", value))?;
+    match ChannelId::new(id).to_channel(ctx).await {
+        Ok(Channel::Guild(channel)) if channel.guild_id == guild_id => Ok(()),
+        Ok(_) => Err(format!("<#{}> is not a channel in this server", id)),
+        Err(e) => Err(format!("could not look up channel {}: {}", id, e)),
+    }
+}
+
+async fn config_set(
+    ctx: &Context,
+    engine: &ModerationEngine,
+    guild_id: GuildId,
+    key: &str,
+    value: &str,
+) -> String {
+    if CHANNEL_SETTINGS.contains(&key) {
+        if let Err(e) = check_channel(ctx, guild_id, value).await {
+            return format!("Config unchanged: {}", e);
+        }
+    }
+
+    let set = {
+        let config = engine.config.clone();
+        let (key, value) = (key.to_string(), value.to_string());
+        tokio::task::spawn_blocking(move || config.set(guild_id, &key, &value)).await
+    };
+    match set {
+        Ok(Ok(())) => {
+            log::info!("Set {} = {} for guild {}", key, value, guild_id);
+            format!("Set `{}` to `{}` for this server.", key, value)
+        }
+        Ok(Err(e)) => format!("Config unchanged: {}", e),
+        Err(e) => format!("Config unchanged: {}", e),
+    }
+}
+
+fn status(engine: &ModerationEngine, guild_id: GuildId) -> String {
+    let counts = match engine.store.action_counts(guild_id, 60 * 60 * 24) {
+        Ok(counts) if counts.is_empty() => "nothing scored".to_string(),
+        Ok(counts) => counts
+            .iter()
+            .map(|(action, count)| format!("{} {}", action, count))
+            .collect::<Vec<_>>()
+            .join(", "),
+        Err(e) => format!("unavailable ({})", e),
+    };
+
+    let current = engine.config.current();
+    let config = current.for_guild(Some(guild_id));
+    let mut mode = config.mode.to_string();
+    for (channel, channel_mode) in &config.channel_modes {
+        mode.push_str(&format!(", {} in <#{}>", channel_mode, channel));
+    }
+    let mut model = match engine.provider(&config.provider) {
+        Ok(provider) => format!("{} ({})", provider.model_name(), config.provider),
+        Err(e) => format!("unavailable ({})", e),
+    };
+    if !config.fallback.is_empty() {
+        model.push_str(&format!(", falling back to {}", config.fallback.join(", ")));
+    }
+    if config.ensemble.strategy != EnsembleStrategy::Off {
+        model.push_str(&format!(
+            ", {} ensemble with {}",
+            config.ensemble.strategy,
+            config.ensemble.providers.join(", ")
+        ));
+    }
+
+    format!(
+        "***Mode: ***{}\n***Model: ***{}\n***Last 24h: ***{}\n\nBot-wide, across all servers:\n***Queue: ***{} waiting, {} dropped, {} pre-filtered\n***Requests: ***{} sent, {} retries, {} given up\n***Pre-filter: ***{} clean, {} blocked",
+        mode,
+        model,
+        counts,
+        METRICS.queue_depth.load(Ordering::Relaxed),
+        METRICS.queue_dropped.load(Ordering::Relaxed),
+        METRICS.queue_prefiltered.load(Ordering::Relaxed),
+        METRICS.provider_requests.load(Ordering::Relaxed),
+        METRICS.provider_retries.load(Ordering::Relaxed),
+        METRICS.provider_give_ups.load(Ordering::Relaxed),
+        METRICS.prefilter_clean.load(Ordering::Relaxed),
+        METRICS.prefilter_blocked.load(Ordering::Relaxed)
+    )
+}
+
+fn history(engine: &ModerationEngine, guild_id: GuildId, user: &User) -> String {
+    let current = engine.config.current();
+    let config = current.for_guild(Some(guild_id));
+
+    let strikes = engine
+        .store
+        .strikes(guild_id, user.id, config.escalation.half_life_hours);
+    let decisions = engine.store.history(guild_id, user.id, HISTORY_LIMIT);
+    let escalations = engine
+        .store
+        .escalation_history(guild_id, user.id, HISTORY_LIMIT);
+    let (strikes, decisions, escalations) = match (strikes, decisions, escalations) {
+        (Ok(strikes), Ok(decisions), Ok(escalations)) => (strikes, decisions, escalations),
+        (Err(e), _, _) | (_, Err(e), _) | (_, _, Err(e)) => {
+            return format!("Could not read the history: {}", e)
+        }
+    };
+
+    let mut text = format!("**{}**: {:.1} strikes\n", user.tag(), strikes);
+    if decisions.is_empty() {
+        text.push_str("No violations on record.\n");
+    }
+    for d in decisions {
+        text.push_str(&format!(
+            "<t:{}:R> {}{} ({}) in <#{}>: {}\n",
+            d.created_at,
+            d.action,
+            if d.edited { " (edit)" } else { "" },
+            d.score,
+            d.channel_id,
+            d.reason
+        ));
+    }
+    for e in escalations {
+        text.push_str(&format!(
+            "<t:{}:R> **{}**{}{}{}\n",
+            e.created_at,
+            e.action,
+            if e.duration_minutes > 0 {
+                format!(" for {} minutes", e.duration_minutes)
+            } else {
+                String::new()
+            },
+            if e.failed { " (failed)" } else { "" },
+            if e.reversed { " (reversed)" } else { "" }
+        ));
+    }
+    text
+}
+
+async fn test(engine: &ModerationEngine, guild_id: GuildId, text: &str) -> String {
+    match engine.test(Some(guild_id), text).await {
+        Ok((verdict, decision)) => format!(
+            "***Score: ***{}\n***Breakdown: ***{}\n***Would: ***{}\n***Triggered by: ***{}\n***Confidence: ***{:.2}\n***AI Thoughts: ***{}\n***Model: ***{} in {}ms\n***Providers: ***{}",
+            verdict.score,
+            verdict.breakdown(),
+            decision.action,
+            decision.trigger.as_deref().unwrap_or("overall score"),
+            verdict.confidence,
+            verdict.reason,
+            verdict.model,
+            verdict.latency_ms,
+            verdict.provider_summary()
+        ),
+        Err(e) => format!("Could not score the text: {}", e),
+    }
+}
+
+pub async fn handle(ctx: &Context, command: &CommandInteraction, engine: &ModerationEngine) {
+    let Some(guild_id) = command.guild_id else {
+        return;
+    };
+
+    let required = match command.data.name.as_str() {
+        "modconfig" => Permissions::MANAGE_GUILD,
+        _ => Permissions::MODERATE_MEMBERS,
+    };
+    let allowed = command
+        .member
+        .as_ref()
+        .and_then(|m| m.permissions)
+        .is_some_and(|p| p.contains(required));
+
+    if let Err(e) = command.defer_ephemeral(ctx).await {
+        log::error!("Failed to defer /{}: {}", command.data.name, e);
+        return;
+    }
+
+    let options = command.data.options();
+    let content = if !allowed {
+        format!("You need the {} permission to use this command.", required)
+    } else {
+        match command.data.name.as_str() {
+            "modconfig" => match options.first() {
+                Some(ResolvedOption { name: "show", .. }) => config_show(engine, guild_id),
+                Some(ResolvedOption {
+                    name: "set",
+                    value: ResolvedValue::SubCommand(options),
+                    ..
+                }) => match (
+                    string_option(options, "key"),
+                    string_option(options, "value"),
+                ) {
+                    (Some(key), Some(value)) => config_set(ctx, engine, guild_id, key, value).await,
+                    _ => "Both a key and a value are required.".to_string(),
+                },
+                _ => "Unknown subcommand.".to_string(),
+            },
+            "modstatus" => status(engine, guild_id),
+            "modhistory" => match user_option(&options, "user") {
+                Some(user) => history(engine, guild_id, user),
+                None => "A user is required.".to_string(),
+            },
+            "modtest" => match string_option(&options, "text") {
+                Some(text) => test(engine, guild_id, text).await,
+                None => "A text is required.".to_string(),
+            },
+            name => format!("Unknown command /{}.", name),
+        }
+    };
+
+    if let Err(e) = command
+        .edit_response(
+            ctx,
+            EditInteractionResponse::new().content(truncate(content)),
+        )
+        .await
+    {
+        log::error!("Failed to respond to /{}: {}", command.data.name, e);
+    }
+}
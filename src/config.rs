@@ -0,0 +1,1449 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    env, fmt, fs,
+    hash::{Hash, Hasher},
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+    time::SystemTime,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serenity::all::{ChannelId, GuildId};
+
+use crate::{
+    constants::{
+        CONFIG_PATH_ENV, CONFIG_POLL_INTERVAL, CONTEXT_BUFFER_SIZE, DEFAULT_CONFIG_PATH,
+        DEFAULT_PROVIDER, MAX_SCORE, MAX_TIMEOUT_MINUTES, SETTABLE_SETTINGS, STARTUP_ONLY_SETTINGS,
+    },
+    prefilter::BlockPattern,
+    prompt::{PromptExample, PromptTemplate},
+    verdict::CATEGORIES,
+};
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(path, e) => write!(f, "failed to read {}: {}", path.display(), e),
+            Self::Parse(path, e) => write!(f, "failed to parse {}: {}", path.display(), e),
+            Self::Invalid(reason) => write!(f, "invalid config: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ActionConfig {
+    pub delete: bool,
+    pub warn: bool,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CategoryThresholds {
+    pub delete_threshold: Option<u16>,
+    pub warn_threshold: Option<u16>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SafetyConfig {
+    pub enabled: bool,
+    pub blocked_score: u16,
+    pub low_score: u16,
+    pub medium_score: u16,
+    pub high_score: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InjectionConfig {
+    pub enabled: bool,
+    pub heuristic_score: u16,
+    pub canary_score: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ContextConfig {
+    pub turns: usize,
+    pub max_tokens: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Punishment {
+    Timeout,
+    Kick,
+    Ban,
+}
+
+impl fmt::Display for Punishment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Timeout => write!(f, "timeout"),
+            Self::Kick => write!(f, "kick"),
+            Self::Ban => write!(f, "ban"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EscalationStep {
+    pub strikes: f32,
+    pub action: Punishment,
+    #[serde(default)]
+    pub duration_minutes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EscalationConfig {
+    pub enabled: bool,
+    pub warn_strikes: f32,
+    pub delete_strikes: f32,
+    pub half_life_hours: f32,
+    pub steps: Vec<EscalationStep>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PromptConfig {
+    pub template: String,
+    pub language: String,
+    pub rules: Vec<String>,
+    pub examples: Vec<PromptExample>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentStorage {
+    None,
+    Hash,
+    Full,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    Enforce,
+    Shadow,
+    Off,
+}
+
+impl fmt::Display for Mode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Enforce => write!(f, "enforce"),
+            Self::Shadow => write!(f, "shadow"),
+            Self::Off => write!(f, "off"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    Gemini,
+    OpenAi,
+}
+
+impl fmt::Display for ProviderKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Gemini => write!(f, "gemini"),
+            Self::OpenAi => write!(f, "openai"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EnsembleStrategy {
+    Off,
+    Max,
+    Mean,
+    Majority,
+    GreyZone,
+}
+
+impl fmt::Display for EnsembleStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Off => write!(f, "off"),
+            Self::Max => write!(f, "max"),
+            Self::Mean => write!(f, "mean"),
+            Self::Majority => write!(f, "majority"),
+            Self::GreyZone => write!(f, "grey_zone"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EnsembleConfig {
+    pub strategy: EnsembleStrategy,
+    pub providers: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PrefilterConfig {
+    pub enabled: bool,
+    pub min_length: usize,
+    pub allow: Vec<String>,
+    pub safe_repeats: u32,
+    pub block_words: Vec<String>,
+    pub block_patterns: Vec<BlockPattern>,
+    pub block_score: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GuildConfig {
+    pub mode: Mode,
+    pub channel_modes: BTreeMap<String, Mode>,
+    pub provider: String,
+    pub fallback: Vec<String>,
+    pub ensemble: EnsembleConfig,
+    pub prefilter: PrefilterConfig,
+    pub delete_threshold: u16,
+    pub warn_threshold: u16,
+    pub categories: BTreeMap<String, CategoryThresholds>,
+    pub mod_log_channel: Option<ChannelId>,
+    pub debug_log_channel: Option<ChannelId>,
+    pub appeals_channel: Option<ChannelId>,
+    pub store_content: ContentStorage,
+    pub actions: ActionConfig,
+    pub safety: SafetyConfig,
+    pub injection: InjectionConfig,
+    pub context: ContextConfig,
+    pub escalation: EscalationConfig,
+    pub prompt: PromptConfig,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ActionOverride {
+    delete: Option<bool>,
+    warn: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SafetyOverride {
+    enabled: Option<bool>,
+    blocked_score: Option<u16>,
+    low_score: Option<u16>,
+    medium_score: Option<u16>,
+    high_score: Option<u16>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InjectionOverride {
+    enabled: Option<bool>,
+    heuristic_score: Option<u16>,
+    canary_score: Option<u16>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ContextOverride {
+    turns: Option<usize>,
+    max_tokens: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EscalationOverride {
+    enabled: Option<bool>,
+    warn_strikes: Option<f32>,
+    delete_strikes: Option<f32>,
+    half_life_hours: Option<f32>,
+    steps: Option<Vec<EscalationStep>>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PromptOverride {
+    template: Option<String>,
+    language: Option<String>,
+    rules: Option<Vec<String>>,
+    examples: Option<Vec<PromptExample>>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EnsembleOverride {
+    strategy: Option<EnsembleStrategy>,
+    providers: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PrefilterOverride {
+    enabled: Option<bool>,
+    min_length: Option<usize>,
+    allow: Option<Vec<String>>,
+    safe_repeats: Option<u32>,
+    block_words: Option<Vec<String>>,
+    block_patterns: Option<Vec<BlockPattern>>,
+    block_score: Option<u16>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GuildOverride {
+    mode: Option<Mode>,
+    #[serde(default)]
+    channel_modes: BTreeMap<String, Mode>,
+    provider: Option<String>,
+    fallback: Option<Vec<String>>,
+    #[serde(default)]
+    ensemble: EnsembleOverride,
+    #[serde(default)]
+    prefilter: PrefilterOverride,
+    delete_threshold: Option<u16>,
+    warn_threshold: Option<u16>,
+    mod_log_channel: Option<ChannelId>,
+    debug_log_channel: Option<ChannelId>,
+    appeals_channel: Option<ChannelId>,
+    store_content: Option<ContentStorage>,
+    #[serde(default)]
+    actions: ActionOverride,
+    #[serde(default)]
+    categories: BTreeMap<String, CategoryThresholds>,
+    #[serde(default)]
+    safety: SafetyOverride,
+    #[serde(default)]
+    injection: InjectionOverride,
+    #[serde(default)]
+    context: ContextOverride,
+    #[serde(default)]
+    escalation: EscalationOverride,
+    #[serde(default)]
+    prompt: PromptOverride,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RetryConfig {
+    pub max_attempts: u32,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    pub max_total_ms: u64,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 4,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 8_000,
+            max_total_ms: 20_000,
+        }
+    }
+}
+
+impl RetryConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.max_attempts == 0 {
+            return Err(ConfigError::Invalid(
+                "retry: max_attempts must be at least 1".to_string(),
+            ));
+        }
+        if self.initial_backoff_ms > self.max_backoff_ms {
+            return Err(ConfigError::Invalid(format!(
+                "retry: initial_backoff_ms {} is above max_backoff_ms {}",
+                self.initial_backoff_ms, self.max_backoff_ms
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProviderConfig {
+    pub kind: ProviderKind,
+    pub base_url: Option<String>,
+    pub model: Option<String>,
+    pub api_key_env: Option<String>,
+}
+
+impl ProviderConfig {
+    fn validate(&self, name: &str) -> Result<(), ConfigError> {
+        if self.kind == ProviderKind::OpenAi && self.model.is_none() {
+            return Err(ConfigError::Invalid(format!(
+                "providers.{}: openai providers need a model",
+                name
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub rpm: u32,
+    pub rpd: u32,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self { rpm: 60, rpd: 1500 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OverflowPolicy {
+    Drop,
+    Defer,
+    Prefilter,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QueueConfig {
+    pub capacity: usize,
+    pub workers: usize,
+    pub overflow: OverflowPolicy,
+    pub defer_timeout_ms: u64,
+    pub new_account_days: u64,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            capacity: 100,
+            workers: 4,
+            overflow: OverflowPolicy::Defer,
+            defer_timeout_ms: 30_000,
+            new_account_days: 7,
+        }
+    }
+}
+
+impl QueueConfig {
+    fn validate(&self, rate_limit: &RateLimitConfig) -> Result<(), ConfigError> {
+        if rate_limit.rpm == 0 || rate_limit.rpd == 0 {
+            return Err(ConfigError::Invalid(
+                "rate_limit: rpm and rpd must be at least 1".to_string(),
+            ));
+        }
+        if self.capacity == 0 || self.workers == 0 {
+            return Err(ConfigError::Invalid(
+                "queue: capacity and workers must be at least 1".to_string(),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AttachmentConfig {
+    pub enabled: bool,
+    pub max_count: usize,
+    pub max_bytes: u64,
+    pub allowed_types: Vec<String>,
+}
+
+impl Default for AttachmentConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_count: 4,
+            max_bytes: 4 * 1024 * 1024,
+            allowed_types: ["image/png", "image/jpeg", "image/webp", "image/gif"]
+                .iter()
+                .map(|t| t.to_string())
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StoreConfig {
+    pub path: PathBuf,
+    pub retention_days: u64,
+}
+
+impl Default for StoreConfig {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("moderator.db"),
+            retention_days: 90,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    default: GuildOverride,
+    #[serde(default)]
+    guilds: HashMap<String, GuildOverride>,
+    #[serde(default)]
+    providers: HashMap<String, ProviderConfig>,
+    #[serde(default)]
+    retry: RetryConfig,
+    #[serde(default)]
+    rate_limit: RateLimitConfig,
+    #[serde(default)]
+    queue: QueueConfig,
+    #[serde(default)]
+    attachments: AttachmentConfig,
+    #[serde(default)]
+    store: StoreConfig,
+    prompts_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub default: GuildConfig,
+    pub guilds: HashMap<GuildId, GuildConfig>,
+    pub providers: HashMap<String, ProviderConfig>,
+    pub retry: RetryConfig,
+    pub rate_limit: RateLimitConfig,
+    pub queue: QueueConfig,
+    pub attachments: AttachmentConfig,
+    pub store: StoreConfig,
+    pub prompts: HashMap<String, PromptTemplate>,
+}
+
+impl GuildConfig {
+    fn apply(&self, o: &GuildOverride) -> Self {
+        let mut categories = self.categories.clone();
+        for (name, thresholds) in &o.categories {
+            let entry = categories.entry(name.clone()).or_default();
+            entry.delete_threshold = thresholds.delete_threshold.or(entry.delete_threshold);
+            entry.warn_threshold = thresholds.warn_threshold.or(entry.warn_threshold);
+        }
+
+        let mut channel_modes = self.channel_modes.clone();
+        channel_modes.extend(o.channel_modes.clone());
+
+        Self {
+            mode: o.mode.unwrap_or(self.mode),
+            channel_modes,
+            provider: o.provider.clone().unwrap_or_else(|| self.provider.clone()),
+            fallback: o.fallback.clone().unwrap_or_else(|| self.fallback.clone()),
+            ensemble: EnsembleConfig {
+                strategy: o.ensemble.strategy.unwrap_or(self.ensemble.strategy),
+                providers: o
+                    .ensemble
+                    .providers
+                    .clone()
+                    .unwrap_or_else(|| self.ensemble.providers.clone()),
+            },
+            prefilter: PrefilterConfig {
+                enabled: o.prefilter.enabled.unwrap_or(self.prefilter.enabled),
+                min_length: o.prefilter.min_length.unwrap_or(self.prefilter.min_length),
+                allow: o
+                    .prefilter
+                    .allow
+                    .clone()
+                    .unwrap_or_else(|| self.prefilter.allow.clone()),
+                safe_repeats: o
+                    .prefilter
+                    .safe_repeats
+                    .unwrap_or(self.prefilter.safe_repeats),
+                block_words: o
+                    .prefilter
+                    .block_words
+                    .clone()
+                    .unwrap_or_else(|| self.prefilter.block_words.clone()),
+                block_patterns: o
+                    .prefilter
+                    .block_patterns
+                    .clone()
+                    .unwrap_or_else(|| self.prefilter.block_patterns.clone()),
+                block_score: o
+                    .prefilter
+                    .block_score
+                    .unwrap_or(self.prefilter.block_score),
+            },
+            delete_threshold: o.delete_threshold.unwrap_or(self.delete_threshold),
+            warn_threshold: o.warn_threshold.unwrap_or(self.warn_threshold),
+            categories,
+            mod_log_channel: o.mod_log_channel.or(self.mod_log_channel),
+            debug_log_channel: o.debug_log_channel.or(self.debug_log_channel),
+            appeals_channel: o.appeals_channel.or(self.appeals_channel),
+            store_content: o.store_content.unwrap_or(self.store_content),
+            actions: ActionConfig {
+                delete: o.actions.delete.unwrap_or(self.actions.delete),
+                warn: o.actions.warn.unwrap_or(self.actions.warn),
+            },
+            safety: SafetyConfig {
+                enabled: o.safety.enabled.unwrap_or(self.safety.enabled),
+                blocked_score: o.safety.blocked_score.unwrap_or(self.safety.blocked_score),
+                low_score: o.safety.low_score.unwrap_or(self.safety.low_score),
+                medium_score: o.safety.medium_score.unwrap_or(self.safety.medium_score),
+                high_score: o.safety.high_score.unwrap_or(self.safety.high_score),
+            },
+            injection: InjectionConfig {
+                enabled: o.injection.enabled.unwrap_or(self.injection.enabled),
+                heuristic_score: o
+                    .injection
+                    .heuristic_score
+                    .unwrap_or(self.injection.heuristic_score),
+                canary_score: o
+                    .injection
+                    .canary_score
+                    .unwrap_or(self.injection.canary_score),
+            },
+            context: ContextConfig {
+                turns: o.context.turns.unwrap_or(self.context.turns),
+                max_tokens: o.context.max_tokens.unwrap_or(self.context.max_tokens),
+            },
+            escalation: EscalationConfig {
+                enabled: o.escalation.enabled.unwrap_or(self.escalation.enabled),
+                warn_strikes: o
+                    .escalation
+                    .warn_strikes
+                    .unwrap_or(self.escalation.warn_strikes),
+                delete_strikes: o
+                    .escalation
+                    .delete_strikes
+                    .unwrap_or(self.escalation.delete_strikes),
+                half_life_hours: o
+                    .escalation
+                    .half_life_hours
+                    .unwrap_or(self.escalation.half_life_hours),
+                steps: o
+                    .escalation
+                    .steps
+                    .clone()
+                    .unwrap_or_else(|| self.escalation.steps.clone()),
+            },
+            prompt: PromptConfig {
+                template: o
+                    .prompt
+                    .template
+                    .clone()
+                    .unwrap_or_else(|| self.prompt.template.clone()),
+                language: o
+                    .prompt
+                    .language
+                    .clone()
+                    .unwrap_or_else(|| self.prompt.language.clone()),
+                rules: o
+                    .prompt
+                    .rules
+                    .clone()
+                    .unwrap_or_else(|| self.prompt.rules.clone()),
+                examples: o
+                    .prompt
+                    .examples
+                    .clone()
+                    .unwrap_or_else(|| self.prompt.examples.clone()),
+            },
+        }
+    }
+
+    pub fn thresholds(&self, category: &str) -> (u16, u16) {
+        let overrides = self.categories.get(category);
+        (
+            overrides
+                .and_then(|c| c.delete_threshold)
+                .unwrap_or(self.delete_threshold),
+            overrides
+                .and_then(|c| c.warn_threshold)
+                .unwrap_or(self.warn_threshold),
+        )
+    }
+
+    pub fn mode_for(&self, channel_id: ChannelId) -> Mode {
+        self.channel_modes
+            .get(&channel_id.to_string())
+            .copied()
+            .unwrap_or(self.mode)
+    }
+
+    fn validate(
+        &self,
+        scope: &str,
+        providers: &HashMap<String, ProviderConfig>,
+    ) -> Result<(), ConfigError> {
+        validate_thresholds(scope, self.delete_threshold, self.warn_threshold)?;
+        let named = std::iter::once(("provider", &self.provider))
+            .chain(self.fallback.iter().map(|name| ("fallback", name)))
+            .chain(
+                self.ensemble
+                    .providers
+                    .iter()
+                    .map(|name| ("ensemble", name)),
+            );
+        for (key, name) in named {
+            if !providers.contains_key(name) {
+                let mut names = providers.keys().collect::<Vec<_>>();
+                names.sort();
+                return Err(ConfigError::Invalid(format!(
+                    "{}.{}: unknown provider {:?}, expected one of {:?}",
+                    scope, key, name, names
+                )));
+            }
+        }
+        if self.ensemble.strategy != EnsembleStrategy::Off && self.ensemble.providers.is_empty() {
+            return Err(ConfigError::Invalid(format!(
+                "{}.ensemble: strategy {} needs at least one provider to ask",
+                scope, self.ensemble.strategy
+            )));
+        }
+        for key in self.channel_modes.keys() {
+            if !key.parse::<u64>().is_ok_and(|id| id != 0) {
+                return Err(ConfigError::Invalid(format!(
+                    "{}.channel_modes.{}: not a channel id",
+                    scope, key
+                )));
+            }
+        }
+        for name in self.categories.keys() {
+            if !CATEGORIES.contains(&name.as_str()) {
+                return Err(ConfigError::Invalid(format!(
+                    "{}.categories: unknown category {:?}, expected one of {:?}",
+                    scope, name, CATEGORIES
+                )));
+            }
+            let (delete, warn) = self.thresholds(name);
+            validate_thresholds(&format!("{}.categories.{}", scope, name), delete, warn)?;
+        }
+
+        let safety = &self.safety;
+        let scores = [
+            safety.blocked_score,
+            safety.low_score,
+            safety.medium_score,
+            safety.high_score,
+        ];
+        if scores.iter().any(|score| *score > MAX_SCORE) {
+            return Err(ConfigError::Invalid(format!(
+                "{}.safety: scores must not be above {}",
+                scope, MAX_SCORE
+            )));
+        }
+        if self.prefilter.block_score > MAX_SCORE {
+            return Err(ConfigError::Invalid(format!(
+                "{}.prefilter: block_score {} is above the maximum score {}",
+                scope, self.prefilter.block_score, MAX_SCORE
+            )));
+        }
+        if self.injection.heuristic_score > MAX_SCORE || self.injection.canary_score > MAX_SCORE {
+            return Err(ConfigError::Invalid(format!(
+                "{}.injection: scores must not be above {}",
+                scope, MAX_SCORE
+            )));
+        }
+        if safety.low_score > safety.medium_score || safety.medium_score > safety.high_score {
+            return Err(ConfigError::Invalid(format!(
+                "{}.safety: expected low_score <= medium_score <= high_score",
+                scope
+            )));
+        }
+
+        if self.context.turns > CONTEXT_BUFFER_SIZE {
+            return Err(ConfigError::Invalid(format!(
+                "{}.context: turns {} is above the buffer size {}",
+                scope, self.context.turns, CONTEXT_BUFFER_SIZE
+            )));
+        }
+
+        let escalation = &self.escalation;
+        if !(escalation.warn_strikes >= 0.0 && escalation.delete_strikes >= 0.0) {
+            return Err(ConfigError::Invalid(format!(
+                "{}.escalation: strike weights must not be negative",
+                scope
+            )));
+        }
+        if escalation.half_life_hours.is_nan() || escalation.half_life_hours <= 0.0 {
+            return Err(ConfigError::Invalid(format!(
+                "{}.escalation: half_life_hours must be above 0",
+                scope
+            )));
+        }
+        for (i, step) in escalation.steps.iter().enumerate() {
+            if step.strikes.is_nan() || step.strikes <= 0.0 {
+                return Err(ConfigError::Invalid(format!(
+                    "{}.escalation.steps[{}]: strikes must be above 0",
+                    scope, i
+                )));
+            }
+            if i > 0 && step.strikes <= escalation.steps[i - 1].strikes {
+                return Err(ConfigError::Invalid(format!(
+                    "{}.escalation.steps[{}]: steps must be in increasing order of strikes",
+                    scope, i
+                )));
+            }
+            if step.action == Punishment::Timeout
+                && !(1..=MAX_TIMEOUT_MINUTES).contains(&step.duration_minutes)
+            {
+                return Err(ConfigError::Invalid(format!(
+                    "{}.escalation.steps[{}]: timeouts need a duration_minutes from 1 to {}",
+                    scope, i, MAX_TIMEOUT_MINUTES
+                )));
+            }
+        }
+
+        let template = &self.prompt.template;
+        if template.is_empty()
+            || !template
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+        {
+            return Err(ConfigError::Invalid(format!(
+                "{}.prompt: template {:?} must be a file name without extension",
+                scope, template
+            )));
+        }
+        if self.prompt.rules.is_empty() {
+            return Err(ConfigError::Invalid(format!(
+                "{}.prompt: at least one rule is required",
+                scope
+            )));
+        }
+        Ok(())
+    }
+}
+
+static DEFAULT_RULES: [&str; 3] = [
+    "Treat everyone with respect. Absolutely no harassment, witch hunting, sexism, racism, or hate speech will be tolerated.",
+    "No spam or self-promotion (server invites, advertisements, etc) without permission from a staff member. However, please do not interpret just posting a URL as advertising.",
+    "No age-restricted or obscene content. This includes text, images, or links featuring nudity, sex, hard violence, or other graphically disturbing content.",
+];
+
+static DEFAULT_EXAMPLES: [(&str, &str); 3] = [
+    (
+        "wtf",
+        r#"{"score": 400, "categories": [{"category": "harassment", "score": 400}], "reason": "possibly offensive language", "rule_violated": "Treat everyone with respect", "confidence": 0.6, "message_id": "<id of the message tag>"}"#,
+    ),
+    (
+        "Here is",
+        r#"{"score": 0, "categories": [], "reason": "", "confidence": 0.95, "message_id": "<id of the message tag>"}"#,
+    ),
+    (
+        "ちんちん",
+        r#"{"score": 700, "categories": [{"category": "sexual", "score": 700}], "reason": "possibly sexually explicit language", "rule_violated": "No age-restricted or obscene content", "confidence": 0.8, "message_id": "<id of the message tag>"}"#,
+    ),
+];
+
+fn validate_thresholds(scope: &str, delete: u16, warn: u16) -> Result<(), ConfigError> {
+    if delete > MAX_SCORE {
+        return Err(ConfigError::Invalid(format!(
+            "{}: delete_threshold {} is above the maximum score {}",
+            scope, delete, MAX_SCORE
+        )));
+    }
+    if warn > delete {
+        return Err(ConfigError::Invalid(format!(
+            "{}: warn_threshold {} is above delete_threshold {}",
+            scope, warn, delete
+        )));
+    }
+    Ok(())
+}
+
+impl Default for GuildConfig {
+    fn default() -> Self {
+        Self {
+            mode: Mode::Enforce,
+            channel_modes: BTreeMap::new(),
+            provider: DEFAULT_PROVIDER.to_string(),
+            fallback: vec![],
+            ensemble: EnsembleConfig {
+                strategy: EnsembleStrategy::Off,
+                providers: vec![],
+            },
+            prefilter: PrefilterConfig {
+                enabled: true,
+                min_length: 1,
+                allow: vec![],
+                safe_repeats: 0,
+                block_words: vec![],
+                block_patterns: vec![],
+                block_score: MAX_SCORE,
+            },
+            delete_threshold: 850,
+            warn_threshold: 650,
+            categories: BTreeMap::new(),
+            mod_log_channel: None,
+            debug_log_channel: None,
+            appeals_channel: None,
+            store_content: ContentStorage::Hash,
+            actions: ActionConfig {
+                delete: true,
+                warn: true,
+            },
+            safety: SafetyConfig {
+                enabled: true,
+                blocked_score: 900,
+                low_score: 0,
+                medium_score: 500,
+                high_score: 850,
+            },
+            injection: InjectionConfig {
+                enabled: true,
+                heuristic_score: 650,
+                canary_score: 650,
+            },
+            context: ContextConfig {
+                turns: 5,
+                max_tokens: 500,
+            },
+            escalation: EscalationConfig {
+                enabled: false,
+                warn_strikes: 1.0,
+                delete_strikes: 2.0,
+                half_life_hours: 72.0,
+                steps: vec![
+                    EscalationStep {
+                        strikes: 3.0,
+                        action: Punishment::Timeout,
+                        duration_minutes: 60,
+                    },
+                    EscalationStep {
+                        strikes: 6.0,
+                        action: Punishment::Timeout,
+                        duration_minutes: 60 * 24,
+                    },
+                    EscalationStep {
+                        strikes: 9.0,
+                        action: Punishment::Kick,
+                        duration_minutes: 0,
+                    },
+                    EscalationStep {
+                        strikes: 12.0,
+                        action: Punishment::Ban,
+                        duration_minutes: 0,
+                    },
+                ],
+            },
+            prompt: PromptConfig {
+                template: "default".to_string(),
+                language: "English".to_string(),
+                rules: DEFAULT_RULES.iter().map(|r| r.to_string()).collect(),
+                examples: DEFAULT_EXAMPLES
+                    .iter()
+                    .map(|(message, verdict)| PromptExample {
+                        message: message.to_string(),
+                        verdict: verdict.to_string(),
+                    })
+                    .collect(),
+            },
+        }
+    }
+}
+
+impl Config {
+    pub fn path() -> PathBuf {
+        env::var(CONFIG_PATH_ENV)
+            .unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_string())
+            .into()
+    }
+
+    pub fn load() -> Result<Self, ConfigError> {
+        let path = Self::path();
+        let text = fs::read_to_string(&path).map_err(|e| ConfigError::Io(path.clone(), e))?;
+        Self::parse(&text, &path)
+    }
+
+    pub fn parse(text: &str, path: &Path) -> Result<Self, ConfigError> {
+        let file: ConfigFile =
+            toml::from_str(text).map_err(|e| ConfigError::Parse(path.to_path_buf(), e))?;
+        Self::from_file(file, path.parent().unwrap_or(Path::new(".")))
+    }
+
+    fn from_file(file: ConfigFile, base_dir: &Path) -> Result<Self, ConfigError> {
+        file.retry.validate()?;
+        file.queue.validate(&file.rate_limit)?;
+
+        let mut providers = file.providers;
+        providers
+            .entry(DEFAULT_PROVIDER.to_string())
+            .or_insert(ProviderConfig {
+                kind: ProviderKind::Gemini,
+                base_url: None,
+                model: None,
+                api_key_env: None,
+            });
+        for (name, provider) in &providers {
+            provider.validate(name)?;
+        }
+
+        let default = GuildConfig::default().apply(&file.default);
+        default.validate("default", &providers)?;
+
+        let mut guilds = HashMap::new();
+        for (key, o) in &file.guilds {
+            let id = key
+                .parse::<u64>()
+                .ok()
+                .filter(|id| *id != 0)
+                .map(GuildId::new)
+                .ok_or_else(|| ConfigError::Invalid(format!("guilds.{}: not a guild id", key)))?;
+            let guild = default.apply(o);
+            guild.validate(&format!("guilds.{}", key), &providers)?;
+            guilds.insert(id, guild);
+        }
+
+        let prompts_dir =
+            base_dir.join(file.prompts_dir.as_deref().unwrap_or(Path::new("prompts")));
+        let mut prompts = HashMap::new();
+        let scoped = std::iter::once(("default".to_string(), &default)).chain(
+            guilds
+                .iter()
+                .map(|(id, guild)| (format!("guilds.{}", id), guild)),
+        );
+        for (scope, guild) in scoped {
+            let name = &guild.prompt.template;
+            if !prompts.contains_key(name) {
+                let template = PromptTemplate::load(&prompts_dir.join(format!("{}.txt", name)))?;
+                prompts.insert(name.clone(), template);
+            }
+            let injection = &guild.injection;
+            if injection.enabled
+                && injection.canary_score > 0
+                && !prompts[name].asks_for_message_id()
+            {
+                return Err(ConfigError::Invalid(format!(
+                    "{}.prompt: template {:?} never asks for message_id, which the injection canary checks (add it or set injection.canary_score = 0)",
+                    scope, name
+                )));
+            }
+        }
+
+        Ok(Self {
+            default,
+            guilds,
+            prompts,
+            providers,
+            retry: file.retry,
+            rate_limit: file.rate_limit,
+            queue: file.queue,
+            attachments: file.attachments,
+            store: StoreConfig {
+                path: base_dir.join(&file.store.path),
+                ..file.store
+            },
+        })
+    }
+
+    pub fn prompt_for(&self, guild: &GuildConfig) -> &PromptTemplate {
+        &self.prompts[&guild.prompt.template]
+    }
+
+    pub fn used_providers(&self) -> Vec<&str> {
+        std::iter::once(&self.default)
+            .chain(self.guilds.values())
+            .flat_map(|g| {
+                std::iter::once(&g.provider)
+                    .chain(&g.fallback)
+                    .chain(&g.ensemble.providers)
+            })
+            .map(|name| name.as_str())
+            .collect()
+    }
+
+    pub fn for_guild(&self, guild_id: Option<GuildId>) -> &GuildConfig {
+        guild_id
+            .and_then(|id| self.guilds.get(&id))
+            .unwrap_or(&self.default)
+    }
+
+    pub fn diff(&self, new: &Config) -> Vec<String> {
+        let mut old_fields = BTreeMap::new();
+        let mut new_fields = BTreeMap::new();
+        self.flatten_into(&mut old_fields);
+        new.flatten_into(&mut new_fields);
+
+        let mut changes = vec![];
+        for (key, old) in &old_fields {
+            match new_fields.get(key) {
+                Some(new) if new != old => changes.push(format!("{}: {} -> {}", key, old, new)),
+                None => changes.push(format!("{}: {} -> (removed)", key, old)),
+                _ => {}
+            }
+        }
+        for (key, new) in &new_fields {
+            if !old_fields.contains_key(key) {
+                changes.push(format!("{}: (added) -> {}", key, new));
+            }
+        }
+        changes
+    }
+
+    fn flatten_into(&self, out: &mut BTreeMap<String, String>) {
+        flatten(
+            "default",
+            &serde_json::to_value(&self.default).unwrap_or_default(),
+            out,
+        );
+        for (id, guild) in &self.guilds {
+            flatten(
+                &format!("guilds.{}", id),
+                &serde_json::to_value(guild).unwrap_or_default(),
+                out,
+            );
+        }
+        for (name, provider) in &self.providers {
+            flatten(
+                &format!("providers.{}", name),
+                &serde_json::to_value(provider).unwrap_or_default(),
+                out,
+            );
+        }
+        flatten(
+            "retry",
+            &serde_json::to_value(&self.retry).unwrap_or_default(),
+            out,
+        );
+        flatten(
+            "rate_limit",
+            &serde_json::to_value(&self.rate_limit).unwrap_or_default(),
+            out,
+        );
+        flatten(
+            "queue",
+            &serde_json::to_value(&self.queue).unwrap_or_default(),
+            out,
+        );
+        flatten(
+            "attachments",
+            &serde_json::to_value(&self.attachments).unwrap_or_default(),
+            out,
+        );
+        flatten(
+            "store",
+            &serde_json::to_value(&self.store).unwrap_or_default(),
+            out,
+        );
+        for (name, template) in &self.prompts {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            template.source().hash(&mut hasher);
+            out.insert(
+                format!("prompts.{}", name),
+                format!("{:016x}", hasher.finish()),
+            );
+        }
+    }
+}
+
+fn flatten(prefix: &str, value: &Value, out: &mut BTreeMap<String, String>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                flatten(&format!("{}.{}", prefix, key), value, out);
+            }
+        }
+        value => {
+            out.insert(prefix.to_string(), value.to_string());
+        }
+    }
+}
+
+// `writes` serializes `set`, so two edits of the file cannot overwrite each
+// other.
+#[derive(Clone)]
+pub struct SharedConfig {
+    config: Arc<RwLock<Arc<Config>>>,
+    writes: Arc<Mutex<()>>,
+}
+
+impl SharedConfig {
+    pub fn new(config: Config) -> Self {
+        Self {
+            config: Arc::new(RwLock::new(Arc::new(config))),
+            writes: Arc::default(),
+        }
+    }
+
+    pub fn current(&self) -> Arc<Config> {
+        self.config.read().unwrap().clone()
+    }
+
+    pub fn reload(&self) {
+        let new = match Config::load() {
+            Ok(config) => config,
+            Err(e) => {
+                log::error!("Config reload failed, keeping the previous config: {}", e);
+                return;
+            }
+        };
+
+        let changes = self.current().diff(&new);
+        if changes.is_empty() {
+            log::info!("Config reloaded, nothing changed");
+            return;
+        }
+
+        *self.config.write().unwrap() = Arc::new(new);
+        log::info!("Config reloaded with {} change(s):", changes.len());
+        for change in changes {
+            if is_startup_only(&change) {
+                log::warn!("  {} (read at startup only, restart to apply)", change);
+            } else {
+                log::info!("  {}", change);
+            }
+        }
+    }
+
+    // Reads and writes the config file, so call it off the async runtime.
+    pub fn set(&self, guild_id: GuildId, key: &str, value: &str) -> Result<(), ConfigError> {
+        if !is_settable(key) {
+            return Err(ConfigError::Invalid(format!(
+                "{} cannot be set from Discord, edit the config file instead",
+                key
+            )));
+        }
+
+        let _writing = self.writes.lock().unwrap();
+        let path = Config::path();
+        let text = fs::read_to_string(&path).map_err(|e| ConfigError::Io(path.clone(), e))?;
+        let mut doc = text
+            .parse::<toml_edit::DocumentMut>()
+            .map_err(|e| ConfigError::Invalid(format!("{}: {}", path.display(), e)))?;
+
+        let not_a_table = |key: &str| ConfigError::Invalid(format!("{} is not a table", key));
+        let guilds = doc
+            .entry("guilds")
+            .or_insert(toml_edit::table())
+            .as_table_mut()
+            .ok_or_else(|| not_a_table("guilds"))?;
+        guilds.set_implicit(true);
+        let mut table = guilds
+            .entry(&guild_id.to_string())
+            .or_insert(toml_edit::table())
+            .as_table_mut()
+            .ok_or_else(|| not_a_table(&format!("guilds.{}", guild_id)))?;
+
+        let mut parts = key.split('.').peekable();
+        while let Some(part) = parts.next() {
+            if part.is_empty() {
+                return Err(ConfigError::Invalid(format!("invalid key {:?}", key)));
+            }
+            if parts.peek().is_none() {
+                let value = value
+                    .parse::<toml_edit::Value>()
+                    .unwrap_or_else(|_| toml_edit::Value::from(value));
+                table[part] = toml_edit::value(value);
+                break;
+            }
+            table = table
+                .entry(part)
+                .or_insert(toml_edit::table())
+                .as_table_mut()
+                .ok_or_else(|| not_a_table(part))?;
+            table.set_implicit(true);
+        }
+
+        let text = doc.to_string();
+        Config::parse(&text, &path)?;
+        fs::write(&path, text).map_err(|e| ConfigError::Io(path.clone(), e))?;
+        self.reload();
+        Ok(())
+    }
+
+    pub fn watch(&self) {
+        let shared = self.clone();
+        tokio::spawn(async move {
+            let mut modified = modified_time();
+            let mut interval = tokio::time::interval(CONFIG_POLL_INTERVAL);
+
+            #[cfg(unix)]
+            let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+                .expect("Failed to install SIGHUP handler");
+
+            loop {
+                #[cfg(unix)]
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    _ = hangup.recv() => {
+                        log::info!("Received SIGHUP, reloading config");
+                        modified = modified_time();
+                        shared.reload();
+                        continue;
+                    }
+                }
+                #[cfg(not(unix))]
+                interval.tick().await;
+
+                let current = modified_time();
+                if current != modified {
+                    modified = current;
+                    log::info!("Config file changed, reloading");
+                    shared.reload();
+                }
+            }
+        });
+    }
+}
+
+fn is_startup_only(change: &str) -> bool {
+    STARTUP_ONLY_SETTINGS
+        .iter()
+        .any(|prefix| change.starts_with(prefix))
+}
+
+fn is_settable(key: &str) -> bool {
+    SETTABLE_SETTINGS
+        .iter()
+        .any(|setting| match setting.strip_suffix('.') {
+            Some(table) => key
+                .strip_prefix(table)
+                .is_some_and(|rest| rest.starts_with('.')),
+            None => key == *setting,
+        })
+}
+
+fn modified_time() -> Option<SystemTime> {
+    fs::metadata(Config::path()).and_then(|m| m.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Config, ConfigError> {
+        Config::parse(
+            text,
+            &Path::new(env!("CARGO_MANIFEST_DIR")).join("config.toml"),
+        )
+    }
+
+    fn invalid(text: &str) -> String {
+        match parse(text) {
+            Err(ConfigError::Invalid(reason)) => reason,
+            Err(e) => panic!("expected a validation error, got {}", e),
+            Ok(_) => panic!("expected a validation error for {:?}", text),
+        }
+    }
+
+    #[test]
+    fn example_config_is_valid() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("config.example.toml");
+        Config::parse(&fs::read_to_string(&path).unwrap(), &path).unwrap();
+    }
+
+    #[test]
+    fn guilds_inherit_the_default() {
+        let config = parse(
+            "[default]
+            delete_threshold = 900
+            [guilds.\"1\"]
+            warn_threshold = 100",
+        )
+        .unwrap();
+        let guild = config.for_guild(Some(GuildId::new(1)));
+        assert_eq!((guild.delete_threshold, guild.warn_threshold), (900, 100));
+        assert_eq!(guild.provider, DEFAULT_PROVIDER);
+        assert!(config.providers.contains_key(DEFAULT_PROVIDER));
+    }
+
+    #[test]
+    fn thresholds_are_checked() {
+        assert!(invalid("[default]\ndelete_threshold = 1001").contains("above the maximum"));
+        assert!(invalid("[default]\nwarn_threshold = 900").contains("above delete_threshold"));
+        assert!(invalid(
+            "[default]
+            [guilds.\"1\".categories.spam]
+            delete_threshold = 300"
+        )
+        .starts_with("guilds.1.categories.spam"));
+        assert!(
+            invalid("[default.categories.memes]\nwarn_threshold = 1").contains("unknown category")
+        );
+        assert!(invalid("[default.safety]\nlow_score = 900").contains("low_score"));
+    }
+
+    #[test]
+    fn ids_are_checked() {
+        assert!(invalid("[default]\n[guilds.abc]").contains("not a guild id"));
+        assert!(invalid("[default.channel_modes]\n\"0\" = \"off\"").contains("not a channel id"));
+    }
+
+    #[test]
+    fn escalation_steps_are_checked() {
+        assert!(invalid(
+            "[default.escalation]
+            steps = [{ strikes = 3.0, action = \"kick\" }, { strikes = 2.0, action = \"ban\" }]"
+        )
+        .contains("increasing order"));
+        assert!(invalid(
+            "[default.escalation]
+            steps = [{ strikes = 3.0, action = \"timeout\" }]"
+        )
+        .contains("duration_minutes"));
+        assert!(invalid("[default.escalation]\nhalf_life_hours = 0.0").contains("half_life_hours"));
+    }
+
+    #[test]
+    fn providers_are_checked() {
+        assert!(invalid("[default]\nprovider = \"local\"").contains("unknown provider"));
+        assert!(invalid("[default]\nfallback = [\"local\"]").starts_with("default.fallback"));
+        assert!(invalid("[default]\n[providers.local]\nkind = \"openai\"").contains("need a model"));
+        assert!(invalid("[default.ensemble]\nstrategy = \"max\"").contains("at least one provider"));
+        parse(
+            "[providers.local]
+            kind = \"openai\"
+            model = \"llama\"
+            [default.ensemble]
+            strategy = \"grey_zone\"
+            providers = [\"local\"]",
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn prefilter_is_checked() {
+        assert!(matches!(
+            parse("[default.prefilter]\nblock_patterns = [\"(\"]"),
+            Err(ConfigError::Parse(_, e)) if e.to_string().contains("regex parse error")
+        ));
+        assert!(invalid("[default.prefilter]\nblock_score = 2000").contains("block_score"));
+    }
+
+    #[test]
+    fn canary_needs_a_message_id() {
+        let dir = std::env::temp_dir().join(format!("moderator-prompts-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("bare.txt"), "{rules}").unwrap();
+        let text = format!(
+            "prompts_dir = {:?}\n[default.prompt]\ntemplate = \"bare\"",
+            dir.display().to_string()
+        );
+        assert!(invalid(&text).contains("never asks for message_id"));
+        parse(&format!("{}\n[default.injection]\ncanary_score = 0", text)).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn only_listed_keys_are_settable() {
+        assert!(is_settable("warn_threshold"));
+        assert!(is_settable("categories.spam.delete_threshold"));
+        assert!(!is_settable("categories"));
+        assert!(!is_settable("provider"));
+        assert!(!is_settable("store_content"));
+        assert!(!is_settable("prompt.template"));
+    }
+
+    #[test]
+    fn startup_only_changes_are_detected() {
+        let old = parse("[default]").unwrap();
+        let new = parse("[queue]\ncapacity = 5\n[default]\nwarn_threshold = 600").unwrap();
+        let changes = old.diff(&new);
+        assert_eq!(changes.len(), 2);
+        assert_eq!(
+            changes
+                .iter()
+                .filter(|c| is_startup_only(c))
+                .collect::<Vec<_>>(),
+            ["queue.capacity: 100 -> 5"]
+        );
+    }
+}
@@ -1,13 +1,49 @@
-use once_cell::sync::Lazy;
-use serenity::all::ChannelId;
+use std::time::Duration;
 
-pub static DELETE_THRESHOLD: u16 = 850;
-pub static WARN_THRESHOLD: u16 = 1950;
+pub static CONFIG_PATH_ENV: &str = "MODERATOR_CONFIG";
+pub static DEFAULT_CONFIG_PATH: &str = "config.toml";
 
-pub static MOD_LOG_CHANNEL: Lazy<ChannelId> = Lazy::new(|| {
-    ChannelId::new(1113711421839130664)
-});
+pub static MAX_SCORE: u16 = 1000;
+pub static CONFIG_POLL_INTERVAL: Duration = Duration::from_secs(5);
 
-pub static DEBUG_LOG_CHANNEL: Lazy<ChannelId> = Lazy::new(|| {
-    ChannelId::new(1170136488038637599)
-});
\ No newline at end of file
+pub static DEFAULT_GEMINI_BASE_URL: &str = "https://generativelanguage.googleapis.com/v1beta";
+pub static DEFAULT_GEMINI_MODEL: &str = "gemini-1.5-flash";
+pub static DEFAULT_OPENAI_BASE_URL: &str = "https://api.openai.com/v1";
+pub static DEFAULT_PROVIDER: &str = "gemini";
+pub static STARTUP_ONLY_SETTINGS: [&str; 5] = [
+    "rate_limit.",
+    "queue.capacity",
+    "queue.workers",
+    "store.path",
+    "providers.",
+];
+pub static MAX_GIF_PIXELS: u64 = 2048 * 2048;
+// Keys /modconfig set may change. Entries ending in a dot cover a whole table.
+pub static SETTABLE_SETTINGS: [&str; 20] = [
+    "mode",
+    "channel_modes.",
+    "delete_threshold",
+    "warn_threshold",
+    "categories.",
+    "mod_log_channel",
+    "debug_log_channel",
+    "appeals_channel",
+    "actions.",
+    "prompt.language",
+    "prompt.rules",
+    "prefilter.enabled",
+    "prefilter.min_length",
+    "prefilter.allow",
+    "prefilter.block_words",
+    "escalation.enabled",
+    "escalation.warn_strikes",
+    "escalation.delete_strikes",
+    "escalation.half_life_hours",
+    "escalation.steps",
+];
+pub static CHANNEL_SETTINGS: [&str; 3] =
+    ["mod_log_channel", "debug_log_channel", "appeals_channel"];
+pub static MESSAGE_CACHE_SIZE: usize = 200;
+pub static CONTEXT_BUFFER_SIZE: usize = 50;
+pub static MAX_TIMEOUT_MINUTES: u64 = 28 * 24 * 60;
+pub static STORE_PRUNE_INTERVAL: Duration = Duration::from_secs(60 * 60);
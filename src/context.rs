@@ -0,0 +1,107 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+};
+
+use serenity::all::{ChannelId, MessageId};
+
+use crate::{config::ContextConfig, constants::CONTEXT_BUFFER_SIZE, injection::wrap};
+
+#[derive(Debug, Clone)]
+pub struct ContextEntry {
+    pub id: MessageId,
+    pub author: String,
+    pub content: String,
+    pub reply_to: Option<MessageId>,
+}
+
+impl ContextEntry {
+    // Roughly four characters per token, which is close enough for a budget.
+    fn tokens(&self) -> usize {
+        (self.author.chars().count() + self.content.chars().count()).div_ceil(4) + 1
+    }
+}
+
+#[derive(Default)]
+pub struct ContextBuffer {
+    channels: Mutex<HashMap<ChannelId, VecDeque<ContextEntry>>>,
+}
+
+impl ContextBuffer {
+    pub fn record(&self, channel: ChannelId, entry: ContextEntry) {
+        let mut channels = self.channels.lock().unwrap();
+        let buffer = channels.entry(channel).or_default();
+
+        if let Some(existing) = buffer.iter_mut().find(|e| e.id == entry.id) {
+            *existing = entry;
+            return;
+        }
+
+        let at = buffer.partition_point(|e| e.id < entry.id);
+        buffer.insert(at, entry);
+        if buffer.len() > CONTEXT_BUFFER_SIZE {
+            buffer.pop_front();
+        }
+    }
+
+    pub fn before(
+        &self,
+        channel: ChannelId,
+        id: MessageId,
+        config: &ContextConfig,
+    ) -> Vec<ContextEntry> {
+        let channels = self.channels.lock().unwrap();
+        let Some(buffer) = channels.get(&channel) else {
+            return vec![];
+        };
+
+        let mut budget = config.max_tokens;
+        let mut turns = buffer
+            .iter()
+            .rev()
+            .filter(|e| e.id < id)
+            .take(config.turns)
+            .take_while(|e| match budget.checked_sub(e.tokens()) {
+                Some(left) => {
+                    budget = left;
+                    true
+                }
+                None => false,
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        turns.reverse();
+        turns
+    }
+}
+
+pub fn render(nonce: &str, turns: &[ContextEntry], target: &ContextEntry) -> String {
+    let reply = |reply_to: Option<MessageId>| match reply_to {
+        Some(id) => match turns.iter().position(|t| t.id == id) {
+            Some(i) => format!(" (replying to [{}])", i + 1),
+            None => " (replying to an earlier message)".to_string(),
+        },
+        None => String::new(),
+    };
+
+    let mut lines = turns
+        .iter()
+        .enumerate()
+        .map(|(i, t)| {
+            format!(
+                "[{}] {}{}: {}",
+                i + 1,
+                t.author,
+                reply(t.reply_to),
+                t.content
+            )
+        })
+        .collect::<Vec<_>>();
+    lines.push(format!(
+        "[target] {}{}: (the message to score, see below)",
+        target.author,
+        reply(target.reply_to)
+    ));
+
+    wrap("context", nonce, &lines.join("\n"))
+}
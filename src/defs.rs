@@ -1,17 +1,46 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
-use crate::enums::{GeminiFinishReason, GeminiHarmCategory, GeminiSafetyThreshold, GeminiHarmProbability};
+use crate::enums::{GeminiBlockReason, GeminiFinishReason, GeminiHarmCategory, GeminiSafetyThreshold, GeminiHarmProbability, GeminiRole, GeminiSchemaType};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiBlob {
+    pub mime_type: String,
+    pub data: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiFileData {
+    pub mime_type: String,
+    pub file_uri: String,
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct GeminiContentBody {
-    pub text: String,
+#[serde(rename_all = "camelCase")]
+pub enum GeminiPart {
+    Text(String),
+    InlineData(GeminiBlob),
+    FileData(GeminiFileData),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GeminiContent {
-    pub parts: Vec<GeminiContentBody>,
+    #[serde(default)]
+    pub parts: Vec<GeminiPart>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub role: Option<String>,
+    pub role: Option<GeminiRole>,
+}
+
+impl GeminiContent {
+    pub fn text(role: Option<GeminiRole>, text: impl Into<String>) -> Self {
+        Self {
+            parts: vec![GeminiPart::Text(text.into())],
+            role,
+        }
+    }
 }
 
 
@@ -35,12 +64,74 @@ pub struct GeminiPostBodyGenerationConfig {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_output_tokens: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub candidate_count: Option<u8>
+    pub candidate_count: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_mime_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_schema: Option<GeminiSchema>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GeminiSchema {
+    #[serde(rename = "type")]
+    pub schema_type: GeminiSchemaType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(rename = "enum", skip_serializing_if = "Option::is_none")]
+    pub enum_values: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub properties: Option<BTreeMap<String, GeminiSchema>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub required: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub items: Option<Box<GeminiSchema>>,
+}
+
+impl GeminiSchema {
+    pub fn new(schema_type: GeminiSchemaType) -> Self {
+        Self {
+            schema_type,
+            description: None,
+            enum_values: None,
+            properties: None,
+            required: None,
+            items: None,
+        }
+    }
+
+    pub fn description(mut self, description: impl Into<String>) -> Self {
+        self.description = Some(description.into());
+        self
+    }
+
+    pub fn enum_values(mut self, values: Vec<String>) -> Self {
+        self.enum_values = Some(values);
+        self
+    }
+
+    pub fn property(mut self, name: &str, schema: GeminiSchema, required: bool) -> Self {
+        self.properties
+            .get_or_insert_with(BTreeMap::new)
+            .insert(name.to_string(), schema);
+        if required {
+            self.required
+                .get_or_insert_with(Vec::new)
+                .push(name.to_string());
+        }
+        self
+    }
+
+    pub fn items(mut self, schema: GeminiSchema) -> Self {
+        self.items = Some(Box::new(schema));
+        self
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GeminiPostBody {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_instruction: Option<GeminiContent>,
     #[serde(default)]
     pub contents: Vec<GeminiContent>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -52,6 +143,7 @@ pub struct GeminiPostBody {
 impl Default for GeminiPostBody {
     fn default() -> Self {
         Self {
+            system_instruction: None,
             contents: vec![],
             safety_settings: None,
             generation_config: None
@@ -70,7 +162,7 @@ pub struct GeminiSafetyRating {
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GeminiPostResponseCandidate {
-    pub content: GeminiContent,
+    pub content: Option<GeminiContent>,
     pub finish_reason: Option<GeminiFinishReason>,
     pub safety_ratings: Option<Vec<GeminiSafetyRating>>,
     pub token_count: Option<u32>,
@@ -80,5 +172,40 @@ pub struct GeminiPostResponseCandidate {
 #[derive(Debug, Clone, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct GeminiPostResponse {
+    #[serde(default)]
     pub candidates: Vec<GeminiPostResponseCandidate>,
+    pub prompt_feedback: Option<GeminiPromptFeedback>,
+}
+
+impl GeminiPostResponse {
+    pub fn text(&self) -> String {
+        self.candidates
+            .iter()
+            .filter_map(|c| c.content.as_ref())
+            .flat_map(|c| c.parts.iter())
+            .filter_map(|p| match p {
+                GeminiPart::Text(text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GeminiPromptFeedback {
+    pub block_reason: Option<GeminiBlockReason>,
+    pub safety_ratings: Option<Vec<GeminiSafetyRating>>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GeminiErrorResponse {
+    pub error: GeminiErrorBody,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GeminiErrorBody {
+    pub code: u16,
+    pub message: String,
+    pub status: Option<String>,
 }
\ No newline at end of file
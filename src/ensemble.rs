@@ -0,0 +1,136 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    config::{EnsembleStrategy, GuildConfig},
+    policy::{decide, scored, Action},
+    verdict::{CategoryScore, ModerationVerdict},
+};
+
+pub fn in_grey_zone(config: &GuildConfig, verdict: &ModerationVerdict) -> bool {
+    let scores = scored(config, verdict);
+    scores.iter().all(|s| s.score < s.delete) && scores.iter().any(|s| s.score >= s.warn)
+}
+
+fn combine_categories(
+    verdicts: &[ModerationVerdict],
+    merge: impl Fn(&[u16]) -> u16,
+) -> Vec<CategoryScore> {
+    let mut categories = BTreeMap::new();
+    for (i, verdict) in verdicts.iter().enumerate() {
+        for c in &verdict.categories {
+            categories
+                .entry(c.category.clone())
+                .or_insert_with(|| vec![0; verdicts.len()])[i] = c.score;
+        }
+    }
+    categories
+        .into_iter()
+        .map(|(category, scores)| CategoryScore {
+            category,
+            score: merge(&scores),
+        })
+        .filter(|c| c.score > 0)
+        .collect()
+}
+
+fn max(scores: &[u16]) -> u16 {
+    scores.iter().copied().max().unwrap_or_default()
+}
+
+fn mean(scores: &[u16]) -> u16 {
+    if scores.is_empty() {
+        return 0;
+    }
+    let sum = scores.iter().map(|s| *s as f32).sum::<f32>();
+    (sum / scores.len() as f32).round() as u16
+}
+
+// The verdict whose reason and rule the combined verdict carries: the closest
+// in score among those that lead to the same action, or failing that among
+// those that act at all (or do not), the higher one on a tie.
+fn closest<'a>(
+    config: &GuildConfig,
+    combined: &ModerationVerdict,
+    verdicts: &'a [ModerationVerdict],
+) -> &'a ModerationVerdict {
+    let action = decide(config, combined).action;
+    let actions = verdicts
+        .iter()
+        .map(|v| decide(config, v).action)
+        .collect::<Vec<_>>();
+    let pick = |agrees: &dyn Fn(Action) -> bool| {
+        verdicts
+            .iter()
+            .zip(&actions)
+            .filter(|(_, a)| agrees(**a))
+            .map(|(v, _)| v)
+            .min_by_key(|v| (v.score.abs_diff(combined.score), std::cmp::Reverse(v.score)))
+    };
+    pick(&|a| a == action)
+        .or_else(|| pick(&|a| (a == Action::None) == (action == Action::None)))
+        .or_else(|| pick(&|_| true))
+        .unwrap_or(&verdicts[0])
+}
+
+// The first verdict is the primary one.
+pub fn combine(
+    config: &GuildConfig,
+    strategy: EnsembleStrategy,
+    verdicts: Vec<ModerationVerdict>,
+) -> ModerationVerdict {
+    let model = verdicts
+        .iter()
+        .map(|v| v.model.as_str())
+        .collect::<Vec<_>>()
+        .join("+");
+
+    let mut combined = match strategy {
+        EnsembleStrategy::Off => verdicts[0].clone(),
+        EnsembleStrategy::Max | EnsembleStrategy::Mean | EnsembleStrategy::GreyZone => {
+            let merge = if strategy == EnsembleStrategy::Max {
+                max
+            } else {
+                mean
+            };
+            let scores = verdicts.iter().map(|v| v.score).collect::<Vec<_>>();
+            let confidence =
+                verdicts.iter().map(|v| v.confidence).sum::<f32>() / verdicts.len() as f32;
+            let mut combined = verdicts[0].clone();
+            combined.score = merge(&scores);
+            combined.categories = combine_categories(&verdicts, merge);
+            combined.confidence = confidence;
+            let source = closest(config, &combined, &verdicts);
+            combined.reason = source.reason.clone();
+            combined.rule_violated = source.rule_violated.clone();
+            combined
+        }
+        // Ties go to the milder action, so two models that disagree do not
+        // act on their own.
+        EnsembleStrategy::Majority => {
+            let votes = verdicts
+                .iter()
+                .map(|v| decide(config, v).action)
+                .collect::<Vec<_>>();
+            let winner = [Action::None, Action::Warn, Action::Delete]
+                .into_iter()
+                .max_by_key(|action| {
+                    (
+                        votes.iter().filter(|v| *v == action).count(),
+                        std::cmp::Reverse(*action),
+                    )
+                })
+                .unwrap_or(Action::None);
+            verdicts
+                .iter()
+                .zip(&votes)
+                .filter(|(_, vote)| **vote == winner)
+                .map(|(v, _)| v)
+                .max_by_key(|v| v.score)
+                .cloned()
+                .unwrap_or_else(|| verdicts[0].clone())
+        }
+    };
+    combined.message_id = verdicts[0].message_id.clone();
+    combined.model = model;
+    combined
+}
@@ -14,7 +14,9 @@ pub enum GeminiHarmCategory {
     Harassment,
     HateSpeech,
     SexuallyExplicit,
-    DangerousContent
+    DangerousContent,
+    CivicIntegrity,
+    Unknown(String),
 }
 
 impl Display for GeminiHarmCategory {
@@ -30,7 +32,9 @@ impl Display for GeminiHarmCategory {
             Self::Harassment => "HARM_CATEGORY_HARASSMENT",
             Self::HateSpeech => "HARM_CATEGORY_HATE_SPEECH",
             Self::SexuallyExplicit => "HARM_CATEGORY_SEXUALLY_EXPLICIT",
-            Self::DangerousContent => "HARM_CATEGORY_DANGEROUS_CONTENT"
+            Self::DangerousContent => "HARM_CATEGORY_DANGEROUS_CONTENT",
+            Self::CivicIntegrity => "HARM_CATEGORY_CIVIC_INTEGRITY",
+            Self::Unknown(ref s) => s,
         };
         write!(f, "{}", s)
     }
@@ -52,7 +56,8 @@ impl FromStr for GeminiHarmCategory {
             "HARM_CATEGORY_HATE_SPEECH" => Ok(Self::HateSpeech),
             "HARM_CATEGORY_SEXUALLY_EXPLICIT" => Ok(Self::SexuallyExplicit),
             "HARM_CATEGORY_DANGEROUS_CONTENT" => Ok(Self::DangerousContent),
-            _ => Err("undefined harm category"),
+            "HARM_CATEGORY_CIVIC_INTEGRITY" => Ok(Self::CivicIntegrity),
+            s => Ok(Self::Unknown(s.to_string())),
         }
     }
 }
@@ -73,7 +78,9 @@ impl Serialize for GeminiHarmCategory {
             GeminiHarmCategory::Harassment => "HARM_CATEGORY_HARASSMENT",
             GeminiHarmCategory::HateSpeech => "HARM_CATEGORY_HATE_SPEECH",
             GeminiHarmCategory::SexuallyExplicit => "HARM_CATEGORY_SEXUALLY_EXPLICIT",
-            GeminiHarmCategory::DangerousContent => "HARM_CATEGORY_DANGEROUS_CONTENT"
+            GeminiHarmCategory::DangerousContent => "HARM_CATEGORY_DANGEROUS_CONTENT",
+            GeminiHarmCategory::CivicIntegrity => "HARM_CATEGORY_CIVIC_INTEGRITY",
+            GeminiHarmCategory::Unknown(ref s) => s,
         };
         serializer.serialize_str(s)
     }
@@ -109,7 +116,8 @@ impl<'de> Deserialize<'de> for GeminiHarmCategory {
                     "HARM_CATEGORY_HATE_SPEECH" => Ok(GeminiHarmCategory::HateSpeech),
                     "HARM_CATEGORY_SEXUALLY_EXPLICIT" => Ok(GeminiHarmCategory::SexuallyExplicit),
                     "HARM_CATEGORY_DANGEROUS_CONTENT" => Ok(GeminiHarmCategory::DangerousContent),
-                    _ => Err(E::custom(format!("unknown category: {}", value))),
+                    "HARM_CATEGORY_CIVIC_INTEGRITY" => Ok(GeminiHarmCategory::CivicIntegrity),
+                    value => Ok(GeminiHarmCategory::Unknown(value.to_string())),
                 }
             }
         }
@@ -210,7 +218,15 @@ pub enum GeminiFinishReason {
     Stop,
     MaxTokens,
     Safety,
-    Recitation
+    Recitation,
+    Language,
+    Other,
+    Blocklist,
+    ProhibitedContent,
+    Spii,
+    MalformedFunctionCall,
+    ImageSafety,
+    Unknown(String),
 }
 
 impl Display for GeminiFinishReason {
@@ -220,7 +236,15 @@ impl Display for GeminiFinishReason {
             Self::Stop => "STOP",
             Self::MaxTokens => "MAX_TOKENS",
             Self::Safety => "SAFETY",
-            Self::Recitation => "RECITATION"
+            Self::Recitation => "RECITATION",
+            Self::Language => "LANGUAGE",
+            Self::Other => "OTHER",
+            Self::Blocklist => "BLOCKLIST",
+            Self::ProhibitedContent => "PROHIBITED_CONTENT",
+            Self::Spii => "SPII",
+            Self::MalformedFunctionCall => "MALFORMED_FUNCTION_CALL",
+            Self::ImageSafety => "IMAGE_SAFETY",
+            Self::Unknown(ref s) => s,
         };
         write!(f, "{}", s)
     }
@@ -236,7 +260,14 @@ impl FromStr for GeminiFinishReason {
             "MAX_TOKENS" => Ok(Self::MaxTokens),
             "SAFETY" => Ok(Self::Safety),
             "RECITATION" => Ok(Self::Recitation),
-            _ => Err("undefined finish reason"),
+            "LANGUAGE" => Ok(Self::Language),
+            "OTHER" => Ok(Self::Other),
+            "BLOCKLIST" => Ok(Self::Blocklist),
+            "PROHIBITED_CONTENT" => Ok(Self::ProhibitedContent),
+            "SPII" => Ok(Self::Spii),
+            "MALFORMED_FUNCTION_CALL" => Ok(Self::MalformedFunctionCall),
+            "IMAGE_SAFETY" => Ok(Self::ImageSafety),
+            s => Ok(Self::Unknown(s.to_string())),
         }
     }
 }
@@ -245,13 +276,21 @@ impl Serialize for GeminiFinishReason {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: Serializer,
-    { 
+    {
         let s = match *self {
             GeminiFinishReason::Unspecified => "FINISH_REASON_UNSPECIFIED",
             GeminiFinishReason::Stop => "STOP",
             GeminiFinishReason::MaxTokens => "MAX_TOKENS",
             GeminiFinishReason::Safety => "SAFETY",
-            GeminiFinishReason::Recitation => "RECITATION"
+            GeminiFinishReason::Recitation => "RECITATION",
+            GeminiFinishReason::Language => "LANGUAGE",
+            GeminiFinishReason::Other => "OTHER",
+            GeminiFinishReason::Blocklist => "BLOCKLIST",
+            GeminiFinishReason::ProhibitedContent => "PROHIBITED_CONTENT",
+            GeminiFinishReason::Spii => "SPII",
+            GeminiFinishReason::MalformedFunctionCall => "MALFORMED_FUNCTION_CALL",
+            GeminiFinishReason::ImageSafety => "IMAGE_SAFETY",
+            GeminiFinishReason::Unknown(ref s) => s,
         };
         serializer.serialize_str(s)
     }
@@ -261,7 +300,7 @@ impl<'de> Deserialize<'de> for GeminiFinishReason {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: Deserializer<'de>,
-    { 
+    {
         struct GeminiFinishReasonVisitor;
 
         impl<'de> Visitor<'de> for GeminiFinishReasonVisitor {
@@ -274,14 +313,21 @@ impl<'de> Deserialize<'de> for GeminiFinishReason {
             fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
             where
                 E: de::Error,
-            { 
+            {
                 match value {
                     "FINISH_REASON_UNSPECIFIED" => Ok(GeminiFinishReason::Unspecified),
                     "STOP" => Ok(GeminiFinishReason::Stop),
                     "MAX_TOKENS" => Ok(GeminiFinishReason::MaxTokens),
                     "SAFETY" => Ok(GeminiFinishReason::Safety),
                     "RECITATION" => Ok(GeminiFinishReason::Recitation),
-                    _ => Err(E::custom(format!("unknown finish reason: {}", value))),
+                    "LANGUAGE" => Ok(GeminiFinishReason::Language),
+                    "OTHER" => Ok(GeminiFinishReason::Other),
+                    "BLOCKLIST" => Ok(GeminiFinishReason::Blocklist),
+                    "PROHIBITED_CONTENT" => Ok(GeminiFinishReason::ProhibitedContent),
+                    "SPII" => Ok(GeminiFinishReason::Spii),
+                    "MALFORMED_FUNCTION_CALL" => Ok(GeminiFinishReason::MalformedFunctionCall),
+                    "IMAGE_SAFETY" => Ok(GeminiFinishReason::ImageSafety),
+                    value => Ok(GeminiFinishReason::Unknown(value.to_string())),
                 }
             }
         }
@@ -374,4 +420,262 @@ impl<'de> Deserialize<'de> for GeminiHarmProbability {
 
         deserializer.deserialize_str(GeminiHarmProbabilityVisitor)
     }
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeminiBlockReason {
+    Unspecified,
+    Safety,
+    Other,
+    Blocklist,
+    ProhibitedContent,
+    ImageSafety,
+    Unknown(String),
+}
+
+impl Display for GeminiBlockReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match *self {
+            Self::Unspecified => "BLOCK_REASON_UNSPECIFIED",
+            Self::Safety => "SAFETY",
+            Self::Other => "OTHER",
+            Self::Blocklist => "BLOCKLIST",
+            Self::ProhibitedContent => "PROHIBITED_CONTENT",
+            Self::ImageSafety => "IMAGE_SAFETY",
+            Self::Unknown(ref s) => s,
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl FromStr for GeminiBlockReason {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "BLOCK_REASON_UNSPECIFIED" => Ok(Self::Unspecified),
+            "SAFETY" => Ok(Self::Safety),
+            "OTHER" => Ok(Self::Other),
+            "BLOCKLIST" => Ok(Self::Blocklist),
+            "PROHIBITED_CONTENT" => Ok(Self::ProhibitedContent),
+            "IMAGE_SAFETY" => Ok(Self::ImageSafety),
+            s => Ok(Self::Unknown(s.to_string())),
+        }
+    }
+}
+
+impl Serialize for GeminiBlockReason {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let s = match *self {
+            GeminiBlockReason::Unspecified => "BLOCK_REASON_UNSPECIFIED",
+            GeminiBlockReason::Safety => "SAFETY",
+            GeminiBlockReason::Other => "OTHER",
+            GeminiBlockReason::Blocklist => "BLOCKLIST",
+            GeminiBlockReason::ProhibitedContent => "PROHIBITED_CONTENT",
+            GeminiBlockReason::ImageSafety => "IMAGE_SAFETY",
+            GeminiBlockReason::Unknown(ref s) => s,
+        };
+        serializer.serialize_str(s)
+    }
+}
+
+impl<'de> Deserialize<'de> for GeminiBlockReason {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct GeminiBlockReasonVisitor;
+
+        impl<'de> Visitor<'de> for GeminiBlockReasonVisitor {
+            type Value = GeminiBlockReason;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a string representing a block reason")
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                match value {
+                    "BLOCK_REASON_UNSPECIFIED" => Ok(GeminiBlockReason::Unspecified),
+                    "SAFETY" => Ok(GeminiBlockReason::Safety),
+                    "OTHER" => Ok(GeminiBlockReason::Other),
+                    "BLOCKLIST" => Ok(GeminiBlockReason::Blocklist),
+                    "PROHIBITED_CONTENT" => Ok(GeminiBlockReason::ProhibitedContent),
+                    "IMAGE_SAFETY" => Ok(GeminiBlockReason::ImageSafety),
+                    value => Ok(GeminiBlockReason::Unknown(value.to_string())),
+                }
+            }
+        }
+
+        deserializer.deserialize_str(GeminiBlockReasonVisitor)
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeminiSchemaType {
+    String,
+    Number,
+    Integer,
+    Boolean,
+    Array,
+    Object,
+}
+
+impl Display for GeminiSchemaType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match *self {
+            Self::String => "STRING",
+            Self::Number => "NUMBER",
+            Self::Integer => "INTEGER",
+            Self::Boolean => "BOOLEAN",
+            Self::Array => "ARRAY",
+            Self::Object => "OBJECT",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl FromStr for GeminiSchemaType {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "STRING" => Ok(Self::String),
+            "NUMBER" => Ok(Self::Number),
+            "INTEGER" => Ok(Self::Integer),
+            "BOOLEAN" => Ok(Self::Boolean),
+            "ARRAY" => Ok(Self::Array),
+            "OBJECT" => Ok(Self::Object),
+            _ => Err("undefined schema type"),
+        }
+    }
+}
+
+impl Serialize for GeminiSchemaType {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let s = match *self {
+            GeminiSchemaType::String => "STRING",
+            GeminiSchemaType::Number => "NUMBER",
+            GeminiSchemaType::Integer => "INTEGER",
+            GeminiSchemaType::Boolean => "BOOLEAN",
+            GeminiSchemaType::Array => "ARRAY",
+            GeminiSchemaType::Object => "OBJECT",
+        };
+        serializer.serialize_str(s)
+    }
+}
+
+impl<'de> Deserialize<'de> for GeminiSchemaType {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct GeminiSchemaTypeVisitor;
+
+        impl<'de> Visitor<'de> for GeminiSchemaTypeVisitor {
+            type Value = GeminiSchemaType;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a string representing a schema type")
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                match value {
+                    "STRING" => Ok(GeminiSchemaType::String),
+                    "NUMBER" => Ok(GeminiSchemaType::Number),
+                    "INTEGER" => Ok(GeminiSchemaType::Integer),
+                    "BOOLEAN" => Ok(GeminiSchemaType::Boolean),
+                    "ARRAY" => Ok(GeminiSchemaType::Array),
+                    "OBJECT" => Ok(GeminiSchemaType::Object),
+                    _ => Err(E::custom(format!("unknown schema type: {}", value))),
+                }
+            }
+        }
+
+        deserializer.deserialize_str(GeminiSchemaTypeVisitor)
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeminiRole {
+    User,
+    Model,
+}
+
+impl Display for GeminiRole {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match *self {
+            Self::User => "user",
+            Self::Model => "model",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl FromStr for GeminiRole {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "user" => Ok(Self::User),
+            "model" => Ok(Self::Model),
+            _ => Err("undefined role"),
+        }
+    }
+}
+
+impl Serialize for GeminiRole {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let s = match *self {
+            GeminiRole::User => "user",
+            GeminiRole::Model => "model",
+        };
+        serializer.serialize_str(s)
+    }
+}
+
+impl<'de> Deserialize<'de> for GeminiRole {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        struct GeminiRoleVisitor;
+
+        impl<'de> Visitor<'de> for GeminiRoleVisitor {
+            type Value = GeminiRole;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a string representing a content role")
+            }
+
+            fn visit_str<E>(self, value: &str) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                match value {
+                    "user" => Ok(GeminiRole::User),
+                    "model" => Ok(GeminiRole::Model),
+                    _ => Err(E::custom(format!("unknown role: {}", value))),
+                }
+            }
+        }
+
+        deserializer.deserialize_str(GeminiRoleVisitor)
+    }
+}
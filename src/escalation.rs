@@ -0,0 +1,33 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::{
+    config::{EscalationConfig, EscalationStep},
+    policy::Action,
+};
+
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+pub fn decay(strikes: f32, elapsed_secs: u64, half_life_hours: f32) -> f32 {
+    strikes * 0.5f32.powf(elapsed_secs as f32 / 3600.0 / half_life_hours)
+}
+
+pub fn strike_weight(config: &EscalationConfig, action: Action) -> f32 {
+    match action {
+        Action::Delete => config.delete_strikes,
+        Action::Warn => config.warn_strikes,
+        Action::None => 0.0,
+    }
+}
+
+pub fn crossed_step(config: &EscalationConfig, before: f32, after: f32) -> Option<&EscalationStep> {
+    config
+        .steps
+        .iter()
+        .rev()
+        .find(|step| before < step.strikes && step.strikes <= after)
+}
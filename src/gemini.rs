@@ -0,0 +1,347 @@
+use std::{fmt, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use reqwest::StatusCode;
+
+use crate::{
+    constants::{DEFAULT_GEMINI_BASE_URL, DEFAULT_GEMINI_MODEL},
+    defs::{
+        GeminiBlob, GeminiContent, GeminiErrorBody, GeminiErrorResponse, GeminiPart,
+        GeminiPostBody, GeminiPostBodyGenerationConfig, GeminiPostBodySafetySettings,
+        GeminiPostResponse, GeminiSafetyRating,
+    },
+    enums::{
+        GeminiBlockReason, GeminiFinishReason, GeminiHarmCategory, GeminiRole,
+        GeminiSafetyThreshold,
+    },
+    provider::{retry_after, Completion, ModerationProvider, Part, ProviderError, ScoreRequest},
+    ratelimit::RateLimiter,
+    verdict::ModerationVerdict,
+};
+
+#[derive(Debug)]
+pub enum GeminiError {
+    Transport(reqwest::Error),
+    Status {
+        status: StatusCode,
+        error: Option<GeminiErrorBody>,
+        body: String,
+        retry_after: Option<Duration>,
+    },
+    Decode(serde_json::Error),
+    EmptyCandidates,
+    Blocked {
+        reason: Option<GeminiBlockReason>,
+        safety_ratings: Vec<GeminiSafetyRating>,
+    },
+}
+
+impl fmt::Display for GeminiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Transport(e) => write!(f, "transport error: {}", e),
+            Self::Status {
+                status,
+                error: Some(error),
+                ..
+            } => write!(f, "HTTP {}: {}", status, error.message),
+            Self::Status { status, body, .. } => write!(f, "HTTP {}: {}", status, body),
+            Self::Decode(e) => write!(f, "failed to decode response: {}", e),
+            Self::EmptyCandidates => write!(f, "response contained no candidates"),
+            Self::Blocked {
+                reason: Some(reason),
+                ..
+            } => write!(f, "blocked by safety filters ({})", reason),
+            Self::Blocked { .. } => write!(f, "blocked by safety filters"),
+        }
+    }
+}
+
+impl std::error::Error for GeminiError {}
+
+impl GeminiError {
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::Transport(e) => e.is_timeout() || e.is_connect() || e.is_request(),
+            Self::Status { status, .. } => {
+                *status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+            }
+            _ => false,
+        }
+    }
+
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::Status { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+// The URL is dropped so that request details never end up in logs or the
+// store.
+impl From<reqwest::Error> for GeminiError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Transport(e.without_url())
+    }
+}
+
+#[derive(Clone)]
+pub struct GeminiClient {
+    http: reqwest::Client,
+    base_url: String,
+    model: String,
+    api_key: String,
+    limiter: Option<Arc<RateLimiter>>,
+}
+
+impl GeminiClient {
+    pub fn new(api_key: impl Into<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: DEFAULT_GEMINI_BASE_URL.to_string(),
+            model: DEFAULT_GEMINI_MODEL.to_string(),
+            api_key: api_key.into(),
+            limiter: None,
+        }
+    }
+
+    pub fn rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    pub fn base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.model = model.into();
+        self
+    }
+
+    pub async fn generate_content(
+        &self,
+        body: &GeminiPostBody,
+    ) -> Result<GeminiPostResponse, GeminiError> {
+        let res = self
+            .http
+            .post(format!(
+                "{}/models/{}:generateContent",
+                self.base_url, self.model
+            ))
+            .header("x-goog-api-key", &self.api_key)
+            .json(body)
+            .send()
+            .await?;
+
+        let status = res.status();
+        let retry_after = retry_after(&res);
+        let text = res.text().await?;
+
+        if !status.is_success() {
+            return Err(GeminiError::Status {
+                status,
+                retry_after,
+                error: serde_json::from_str::<GeminiErrorResponse>(&text)
+                    .ok()
+                    .map(|e| e.error),
+                body: text,
+            });
+        }
+
+        let res: GeminiPostResponse = serde_json::from_str(&text).map_err(GeminiError::Decode)?;
+
+        if let Some(feedback) = &res.prompt_feedback {
+            if feedback.block_reason.is_some() {
+                return Err(GeminiError::Blocked {
+                    reason: feedback.block_reason.clone(),
+                    safety_ratings: feedback.safety_ratings.clone().unwrap_or_default(),
+                });
+            }
+        }
+
+        let Some(candidate) = res.candidates.first() else {
+            return Err(GeminiError::EmptyCandidates);
+        };
+
+        if let Some(reason) = candidate.finish_reason.as_ref().and_then(blocked_by) {
+            if res.text().is_empty() {
+                return Err(GeminiError::Blocked {
+                    reason: Some(reason),
+                    safety_ratings: candidate.safety_ratings.clone().unwrap_or_default(),
+                });
+            }
+        }
+
+        Ok(res)
+    }
+}
+
+// Candidates stopped by one of Gemini's filters come back without text, and
+// are refusals just like a blocked prompt.
+fn blocked_by(reason: &GeminiFinishReason) -> Option<GeminiBlockReason> {
+    match reason {
+        GeminiFinishReason::Safety => Some(GeminiBlockReason::Safety),
+        GeminiFinishReason::Blocklist => Some(GeminiBlockReason::Blocklist),
+        GeminiFinishReason::ProhibitedContent => Some(GeminiBlockReason::ProhibitedContent),
+        GeminiFinishReason::ImageSafety => Some(GeminiBlockReason::ImageSafety),
+        GeminiFinishReason::Spii => Some(GeminiBlockReason::Unknown(reason.to_string())),
+        _ => None,
+    }
+}
+
+#[async_trait]
+impl ModerationProvider for GeminiClient {
+    fn model_name(&self) -> &str {
+        &self.model
+    }
+
+    fn rate_limiter(&self) -> Option<&RateLimiter> {
+        self.limiter.as_deref()
+    }
+
+    async fn complete(&self, request: &ScoreRequest) -> Result<Completion, ProviderError> {
+        let body = GeminiPostBody {
+            system_instruction: Some(GeminiContent::text(None, request.system.clone())),
+            contents: vec![GeminiContent {
+                parts: request
+                    .parts
+                    .iter()
+                    .map(|part| match part {
+                        Part::Text(text) => GeminiPart::Text(text.clone()),
+                        Part::Image { mime_type, data } => GeminiPart::InlineData(GeminiBlob {
+                            mime_type: mime_type.clone(),
+                            data: data.clone(),
+                        }),
+                    })
+                    .collect(),
+                role: Some(GeminiRole::User),
+            }],
+            safety_settings: Some(
+                [
+                    GeminiHarmCategory::SexuallyExplicit,
+                    GeminiHarmCategory::HateSpeech,
+                    GeminiHarmCategory::Harassment,
+                    GeminiHarmCategory::DangerousContent,
+                ]
+                .into_iter()
+                .map(|category| GeminiPostBodySafetySettings {
+                    category,
+                    threshold: GeminiSafetyThreshold::None,
+                })
+                .collect(),
+            ),
+            generation_config: Some(GeminiPostBodyGenerationConfig {
+                temperature: Some(0.0),
+                response_mime_type: Some("application/json".to_string()),
+                response_schema: Some(ModerationVerdict::schema()),
+                ..Default::default()
+            }),
+        };
+
+        match self.generate_content(&body).await {
+            Ok(res) => Ok(Completion {
+                text: res.text(),
+                safety_ratings: res
+                    .candidates
+                    .first()
+                    .and_then(|c| c.safety_ratings.clone())
+                    .unwrap_or_default(),
+            }),
+            Err(GeminiError::Blocked {
+                reason,
+                safety_ratings,
+            }) => Err(ProviderError::Refused {
+                reason: reason.unwrap_or(GeminiBlockReason::Safety).to_string(),
+                safety_ratings,
+            }),
+            Err(e) => Err(ProviderError::Gemini(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+    use wiremock::{matchers::method, Mock, MockServer, ResponseTemplate};
+
+    use super::*;
+    use crate::enums::GeminiHarmProbability;
+
+    async fn generate(body: Value) -> Result<GeminiPostResponse, GeminiError> {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(body))
+            .mount(&server)
+            .await;
+        GeminiClient::new("key")
+            .base_url(server.uri())
+            .model("test")
+            .generate_content(&GeminiPostBody::default())
+            .await
+    }
+
+    fn block_reason(result: Result<GeminiPostResponse, GeminiError>) -> Option<String> {
+        match result {
+            Err(GeminiError::Blocked { reason, .. }) => reason.map(|r| r.to_string()),
+            other => panic!("expected a block, got {:?}", other.map(|r| r.text())),
+        }
+    }
+
+    #[tokio::test]
+    async fn blocked_prompts_are_refused() {
+        for reason in [
+            "BLOCKLIST",
+            "PROHIBITED_CONTENT",
+            "IMAGE_SAFETY",
+            "SOMETHING_NEW",
+        ] {
+            let result = generate(json!({ "promptFeedback": { "blockReason": reason } })).await;
+            assert_eq!(block_reason(result).as_deref(), Some(reason));
+        }
+    }
+
+    #[tokio::test]
+    async fn filtered_candidates_are_refused() {
+        for reason in [
+            "SAFETY",
+            "BLOCKLIST",
+            "PROHIBITED_CONTENT",
+            "SPII",
+            "IMAGE_SAFETY",
+        ] {
+            let result = generate(json!({ "candidates": [{ "finishReason": reason }] })).await;
+            assert_eq!(block_reason(result).as_deref(), Some(reason));
+        }
+    }
+
+    #[tokio::test]
+    async fn newer_values_still_decode() {
+        let res = generate(json!({
+            "candidates": [{
+                "content": { "parts": [{ "text": "{}" }], "role": "model" },
+                "finishReason": "RECITATION",
+                "safetyRatings": [
+                    { "category": "HARM_CATEGORY_CIVIC_INTEGRITY", "probability": "NEGLIGIBLE" },
+                    { "category": "HARM_CATEGORY_SOMETHING_NEW", "probability": "LOW" }
+                ]
+            }]
+        }))
+        .await
+        .unwrap();
+        let candidate = &res.candidates[0];
+        assert_eq!(
+            candidate.finish_reason,
+            Some(GeminiFinishReason::Recitation)
+        );
+        let ratings = candidate.safety_ratings.as_ref().unwrap();
+        assert_eq!(ratings[0].category, GeminiHarmCategory::CivicIntegrity);
+        assert_eq!(
+            ratings[1].category,
+            GeminiHarmCategory::Unknown("HARM_CATEGORY_SOMETHING_NEW".to_string())
+        );
+        assert_eq!(ratings[1].probability, GeminiHarmProbability::Low);
+    }
+}
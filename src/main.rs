@@ -1,24 +1,48 @@
+mod appeal;
+mod attachments;
+mod commands;
+mod config;
 mod constants;
+mod context;
 mod defs;
+mod engine;
+mod ensemble;
 mod enums;
-
-use std::env;
-
-use defs::GeminiContentBody;
-use serenity::all::{GatewayIntents, Message, Ready};
+mod escalation;
+mod gemini;
+mod injection;
+mod metrics;
+#[cfg(test)]
+mod mock;
+mod moderator;
+mod openai;
+mod policy;
+mod prefilter;
+mod prompt;
+mod provider;
+mod queue;
+mod ratelimit;
+mod review;
+mod store;
+mod verdict;
+
+use std::{env, process, sync::Arc, time::Duration};
+
+use serenity::all::{Command, GatewayIntents, Interaction, Message, MessageUpdateEvent, Ready};
 use serenity::async_trait;
-use serenity::builder::{CreateEmbed, CreateMessage};
 use serenity::client::EventHandler;
-use serenity::model::Color;
 use serenity::prelude::Context;
 
 use crate::{
-    constants::{DEBUG_LOG_CHANNEL, DELETE_THRESHOLD, MOD_LOG_CHANNEL, WARN_THRESHOLD},
-    defs::{
-        GeminiContent, GeminiPostBody, GeminiPostBodyGenerationConfig,
-        GeminiPostBodySafetySettings, GeminiPostResponse,
-    },
-    enums::{GeminiHarmCategory, GeminiSafetyThreshold},
+    config::{Config, Mode, OverflowPolicy, SharedConfig},
+    constants::{MESSAGE_CACHE_SIZE, STORE_PRUNE_INTERVAL},
+    context::ContextBuffer,
+    engine::ModerationEngine,
+    metrics::{incr, METRICS},
+    moderator::context_entry,
+    provider::build_providers,
+    queue::{ModerationQueue, Priority},
+    store::Store,
 };
 
 #[inline]
@@ -30,182 +54,119 @@ fn get_intents() -> GatewayIntents {
     intents
 }
 
-fn generate_embed(verb: &str, msg: &Message, score: u16, reason: &str) -> CreateMessage {
-    let embed = CreateEmbed::default()
-        .title(format!("{}{}", msg.author.tag(), verb))
-        .color(Color::RED)
-        .description(format!(
-            ">>> ***Message: *** :warning: ||{}||\n***Score: ***{}\n***AI Thoughts: ***{}",
-            msg.content
-                .char_indices()
-                .take(100)
-                .map(|f| f.1)
-                .collect::<String>(),
-            score,
-            reason
-        ));
-    CreateMessage::new().embed(embed)
+struct Handler {
+    engine: Arc<ModerationEngine>,
+    queue: Arc<ModerationQueue>,
 }
 
-struct Handler;
-
 #[async_trait]
 impl EventHandler for Handler {
-    async fn ready(&self, _: Context, r: Ready) {
+    async fn ready(&self, ctx: Context, r: Ready) {
         log::info!("Connected as {}", r.user.name);
+
+        if let Err(e) = Command::set_global_commands(&ctx.http, commands::definitions()).await {
+            log::error!("Failed to register commands: {}", e);
+        }
+    }
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        match interaction {
+            Interaction::Command(command) => commands::handle(&ctx, &command, &self.engine).await,
+            Interaction::Component(component) => {
+                if component.data.custom_id.starts_with("appeal:") {
+                    appeal::handle_button(&ctx, &component, &self.engine).await
+                } else {
+                    review::handle(&ctx, &component, &self.engine).await
+                }
+            }
+            Interaction::Modal(modal) => appeal::handle_modal(&ctx, &modal, &self.engine).await,
+            _ => {}
+        }
     }
     async fn message(&self, ctx: Context, msg: Message) {
-        let eligible = !msg.author.bot && !msg.content.is_empty();
-
-        if !eligible {
+        self.enqueue(ctx, msg, None).await;
+    }
+    async fn message_update(
+        &self,
+        ctx: Context,
+        old_if_available: Option<Message>,
+        new: Option<Message>,
+        event: MessageUpdateEvent,
+    ) {
+        // Embed unfurls and pins also arrive as updates, without content.
+        let Some(content) = &event.content else {
+            return;
+        };
+        let unchanged = match &old_if_available {
+            Some(old) => &old.content == content,
+            None => self
+                .engine
+                .store
+                .last_content_matches(event.id, content)
+                .unwrap_or_else(|e| {
+                    log::error!("Failed to look up message {}: {}", event.id, e);
+                    false
+                }),
+        };
+        if unchanged {
             return;
         }
 
-        let client = reqwest::Client::new();
-
-        let body = GeminiPostBody {
-            contents: vec![GeminiContent {
-                parts: vec![GeminiContentBody {
-                    text: format!(
-                        r#"Determine how well the posts sent by users are suitable for posting on social networking sites.
-This networking site has this rules:
-- Treat everyone with respect. Absolutely no harassment, witch hunting, sexism, racism, or hate speech will be tolerated.
-- No spam or self-promotion (server invites, advertisements, etc) without permission from a staff member. However, please do not interpret just posting a URL as advertising.
-- No age-restricted or obscene content. This includes text, images, or links featuring nudity, sex, hard violence, or other graphically disturbing content.
-Decide the score of the content posted by the user based on this rule. (from 0 to 1000) 0 is a very good post that does not violate the rules, and 1000 is a post that violates the rules perfectly.
-
-Do not output 1000 unless there is a clear discriminatory term. They should be on a much lower score.
-Do not output high scores for submissions ex. "a" or "あ". These are probably just tests, and there is nothing wrong with them.
-
-If the score is 0, you don't need to output the reason.
-
-Specified format: score|reason
-Example for "wtf": 400|possibly offensive language
-Example for "Here is": 0|
-Example for "ちんちん": 700|possibly sexually explicit language
-
-Reasons should be output in detail; do not use ambiguous terms such as discriminatory terms.
-
-Post content: 
-{}
-
-Bad score and reason:"#,
-                        msg.content_safe(&ctx.cache)
-                    ),
-                }],
-                role: None,
-            }],
-            safety_settings: Some(vec![
-                GeminiPostBodySafetySettings {
-                    category: GeminiHarmCategory::SexuallyExplicit,
-                    threshold: GeminiSafetyThreshold::None,
-                },
-                GeminiPostBodySafetySettings {
-                    category: GeminiHarmCategory::HateSpeech,
-                    threshold: GeminiSafetyThreshold::None,
-                },
-                GeminiPostBodySafetySettings {
-                    category: GeminiHarmCategory::Harassment,
-                    threshold: GeminiSafetyThreshold::None,
-                },
-                GeminiPostBodySafetySettings {
-                    category: GeminiHarmCategory::DangerousContent,
-                    threshold: GeminiSafetyThreshold::None,
-                },
-            ]),
-            generation_config: Some(GeminiPostBodyGenerationConfig {
-                temperature: Some(0.0),
-                ..Default::default()
-            }),
+        let msg = match new {
+            Some(msg) => msg,
+            None => match event.channel_id.message(&ctx, event.id).await {
+                Ok(msg) => msg,
+                Err(e) => {
+                    log::debug!("Could not fetch edited message {}: {}", event.id, e);
+                    return;
+                }
+            },
         };
 
-        //println!("{:?}", serde_json::to_string(&body).unwrap());
+        self.enqueue(ctx, msg, old_if_available).await;
+    }
+}
 
-        let res = client.post(format!("https://generativelanguage.googleapis.com/v1beta/models/gemini-pro:generateContent?key={}", env::var("GEMINI_API_KEY").unwrap()))
-            .json(&body)
-            .send()
-            .await;
+impl Handler {
+    async fn enqueue(&self, ctx: Context, msg: Message, previous: Option<Message>) {
+        self.engine
+            .context
+            .record(msg.channel_id, context_entry(&ctx, &msg));
 
-        if let Err(e) = res {
-            log::error!("Error: {:?}", e);
-            return;
-        }
-
-        let res = res.unwrap();
+        let mode = self.engine.mode_for(msg.guild_id, msg.channel_id);
+        let eligible = mode != Mode::Off
+            && !msg.author.bot
+            && (!msg.content.is_empty() || !msg.attachments.is_empty());
 
-        if !res.status().is_success() {
-            log::error!("Error: {:?}", res.text().await.unwrap());
+        if !eligible {
             return;
         }
 
-        let res = res.json::<GeminiPostResponse>().await;
+        let queue_config = self.engine.config.current().queue.clone();
+        let priority = Priority::of(&msg, queue_config.new_account_days);
 
-        if let Err(e) = res {
-            log::error!("Error: {:?}", e);
+        let Err(job) = self.queue.try_push(ctx, msg, previous, priority) else {
             return;
-        }
-
-        let res = res.unwrap();
-
-        let mut content = String::new();
+        };
 
-        for candidate in res.candidates {
-            for part in candidate.content.parts {
-                content.push_str(&part.text);
+        match queue_config.overflow {
+            OverflowPolicy::Drop => {
+                incr(&METRICS.queue_dropped);
+                log::warn!("Queue full, dropped message {}", job.msg.id);
+            }
+            OverflowPolicy::Defer => {
+                let timeout = Duration::from_millis(queue_config.defer_timeout_ms);
+                if let Err(job) = self.queue.push_timeout(job, timeout).await {
+                    incr(&METRICS.queue_dropped);
+                    log::warn!(
+                        "Queue still full after {:?}, dropped message {}",
+                        timeout,
+                        job.msg.id
+                    );
+                }
+            }
+            OverflowPolicy::Prefilter => {
+                moderator::prefilter(&job.ctx, &self.engine, &job.msg, job.previous.as_ref()).await;
             }
-        }
-
-        let separated = content.split_once("|");
-
-        if let None = separated {
-            log::error!("Error: {:?}", content);
-            return;
-        }
-
-        let (score, reason) = separated.unwrap();
-
-        let score = score.parse::<u16>().unwrap_or(0);
-
-        DEBUG_LOG_CHANNEL
-            .say(
-                &ctx,
-                format!(
-                    "\n```\n{}\n```\nScore: {}, Reason: {}",
-                    msg.content_safe(&ctx.cache),
-                    score,
-                    reason
-                ),
-            )
-            .await
-            .ok();
-
-        if score >= DELETE_THRESHOLD {
-            msg.delete(&ctx).await.ok();
-            MOD_LOG_CHANNEL
-                .send_message(
-                    &ctx,
-                    generate_embed("'s message has been deleted!", &msg, score, reason),
-                )
-                .await
-                .ok();
-        } else if score >= WARN_THRESHOLD {
-            MOD_LOG_CHANNEL
-                .send_message(
-                    &ctx,
-                    generate_embed("' has been warned!", &msg, score, reason),
-                )
-                .await
-                .ok();
-            msg.author
-                .dm(
-                    &ctx,
-                    CreateMessage::new().content(format!(
-                        "Your message has been warned!\nYour message content: {}\nReason: {}",
-                        msg.content, reason
-                    )),
-                )
-                .await
-                .ok();
         }
     }
 }
@@ -224,13 +185,101 @@ async fn main() {
         })
         .init();
 
+    let config = match Config::load() {
+        Ok(config) => SharedConfig::new(config),
+        Err(e) => {
+            log::error!("Failed to load config: {}", e);
+            process::exit(1);
+        }
+    };
+
+    config.watch();
+
+    let (queue_config, rate_limit, store_config) = {
+        let current = config.current();
+        (
+            current.queue.clone(),
+            current.rate_limit.clone(),
+            current.store.clone(),
+        )
+    };
+
+    let store = match Store::open(&store_config.path) {
+        Ok(store) => store,
+        Err(e) => {
+            log::error!(
+                "Failed to open store {}: {}",
+                store_config.path.display(),
+                e
+            );
+            process::exit(1);
+        }
+    };
+
+    let providers = {
+        let current = config.current();
+        match build_providers(&current.providers, &current.used_providers(), &rate_limit) {
+            Ok(providers) => providers,
+            Err(e) => {
+                log::error!("Failed to load {}", e);
+                process::exit(1);
+            }
+        }
+    };
+
+    let engine = Arc::new(ModerationEngine {
+        config,
+        providers,
+        http: reqwest::Client::new(),
+        context: ContextBuffer::default(),
+        store,
+    });
+    let queue = Arc::new(ModerationQueue::new(queue_config.capacity));
+
+    {
+        let engine = engine.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(STORE_PRUNE_INTERVAL);
+            loop {
+                interval.tick().await;
+                let retention_days = engine.config.current().store.retention_days;
+                let pruned = {
+                    let engine = engine.clone();
+                    tokio::task::spawn_blocking(move || engine.store.prune(retention_days)).await
+                };
+                match pruned {
+                    Ok(Ok(0)) => {}
+                    Ok(Ok(pruned)) => log::info!("Pruned {} old record(s) from the store", pruned),
+                    Ok(Err(e)) => log::error!("Failed to prune the store: {}", e),
+                    Err(e) => log::error!("Store pruning panicked: {}", e),
+                }
+            }
+        });
+    }
+
+    for _ in 0..queue_config.workers {
+        let engine = engine.clone();
+        let queue = queue.clone();
+        tokio::spawn(async move {
+            loop {
+                let job = queue.pop().await;
+                moderator::moderate(&job.ctx, &engine, &job.msg, job.previous.as_ref()).await;
+            }
+        });
+    }
+
+    let mut cache_settings = serenity::cache::Settings::default();
+    cache_settings.max_messages = MESSAGE_CACHE_SIZE;
+
     let token = env::var("DISCORD_TOKEN").expect("Expected a token in the environment");
     let mut client = serenity::Client::builder(token, get_intents())
-        .event_handler(Handler)
+        .cache_settings(cache_settings)
+        .event_handler(Handler { engine, queue })
         .await
         .expect("Err creating client");
 
     if let Err(why) = client.start().await {
         log::error!("Client error: {:?}", why);
+        process::exit(1);
     }
 }
@@ -0,0 +1,33 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+pub struct Metrics {
+    pub provider_requests: AtomicU64,
+    pub provider_retries: AtomicU64,
+    pub provider_give_ups: AtomicU64,
+    pub prefilter_clean: AtomicU64,
+    pub prefilter_blocked: AtomicU64,
+    pub queue_depth: AtomicU64,
+    pub queue_dropped: AtomicU64,
+    pub queue_prefiltered: AtomicU64,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Self {
+            provider_requests: AtomicU64::new(0),
+            provider_retries: AtomicU64::new(0),
+            provider_give_ups: AtomicU64::new(0),
+            prefilter_clean: AtomicU64::new(0),
+            prefilter_blocked: AtomicU64::new(0),
+            queue_depth: AtomicU64::new(0),
+            queue_dropped: AtomicU64::new(0),
+            queue_prefiltered: AtomicU64::new(0),
+        }
+    }
+}
+
+pub static METRICS: Metrics = Metrics::new();
+
+pub fn incr(counter: &AtomicU64) {
+    counter.fetch_add(1, Ordering::Relaxed);
+}
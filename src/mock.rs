@@ -0,0 +1,238 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::{json, Value};
+use wiremock::{
+    matchers::{method, path_regex},
+    Mock, MockServer, Request, Respond, ResponseTemplate,
+};
+
+use crate::{gemini::GeminiClient, openai::OpenAiClient};
+
+static NONCE: Lazy<Regex> = Lazy::new(|| Regex::new(r#"<message id=\\?"(\w+)\\?">"#).unwrap());
+
+#[derive(Debug, Clone, Copy)]
+enum Api {
+    Gemini,
+    OpenAi,
+}
+
+#[derive(Debug, Clone)]
+pub enum Reply {
+    Verdict {
+        score: u16,
+        categories: Vec<(&'static str, u16)>,
+        reason: &'static str,
+    },
+    // A verdict with a made-up category and a key of the model's own.
+    OffSchema {
+        score: u16,
+        category: &'static str,
+    },
+    Status(u16),
+    // A status that asks to be retried after this many seconds.
+    RetryAfter(u16, u64),
+    Safety,
+    // A Gemini candidate stopped by a filter, with this finish reason.
+    Filtered(&'static str),
+    Malformed,
+    EmptyCandidates,
+}
+
+impl Reply {
+    pub fn clean() -> Self {
+        Self::Verdict {
+            score: 0,
+            categories: vec![],
+            reason: "",
+        }
+    }
+
+    fn answer(api: Api, text: &str) -> Value {
+        match api {
+            Api::Gemini => json!({
+                "candidates": [{
+                    "content": { "parts": [{ "text": text }], "role": "model" },
+                    "finishReason": "STOP"
+                }]
+            }),
+            Api::OpenAi => json!({
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": text },
+                    "finish_reason": "stop"
+                }]
+            }),
+        }
+    }
+
+    fn respond(&self, api: Api, nonce: &str) -> ResponseTemplate {
+        match self {
+            Self::Verdict {
+                score,
+                categories,
+                reason,
+            } => {
+                let verdict = json!({
+                    "score": score,
+                    "categories": categories
+                        .iter()
+                        .map(|(category, score)| json!({ "category": category, "score": score }))
+                        .collect::<Vec<_>>(),
+                    "reason": reason,
+                    "confidence": 0.9,
+                    "message_id": nonce
+                });
+                ResponseTemplate::new(200).set_body_json(Self::answer(api, &verdict.to_string()))
+            }
+            Self::OffSchema { score, category } => {
+                let verdict = json!({
+                    "score": score,
+                    "categories": [{ "category": category, "score": score }],
+                    "reason": "rude",
+                    "confidence": 0.9,
+                    "message_id": nonce,
+                    "notes": "the user seems upset"
+                });
+                ResponseTemplate::new(200).set_body_json(Self::answer(api, &verdict.to_string()))
+            }
+            Self::Status(status) => Self::RetryAfter(*status, 0).respond(api, nonce),
+            Self::RetryAfter(status, secs) => ResponseTemplate::new(*status)
+                .insert_header("Retry-After", secs.to_string().as_str())
+                .set_body_json(json!({
+                    "error": { "code": status, "message": "scripted failure", "status": "UNAVAILABLE" }
+                })),
+            Self::Safety => ResponseTemplate::new(200).set_body_json(match api {
+                Api::Gemini => json!({
+                    "candidates": [{
+                        "finishReason": "SAFETY",
+                        "safetyRatings": [
+                            { "category": "HARM_CATEGORY_HARASSMENT", "probability": "HIGH" }
+                        ]
+                    }]
+                }),
+                Api::OpenAi => json!({
+                    "choices": [{
+                        "index": 0,
+                        "message": {
+                            "role": "assistant",
+                            "content": null,
+                            "refusal": "I can't help with that."
+                        },
+                        "finish_reason": "stop"
+                    }]
+                }),
+            }),
+            Self::Filtered(reason) => ResponseTemplate::new(200).set_body_json(match api {
+                Api::Gemini => json!({ "candidates": [{ "finishReason": reason }] }),
+                Api::OpenAi => json!({
+                    "choices": [{
+                        "index": 0,
+                        "message": { "role": "assistant", "content": null, "refusal": reason },
+                        "finish_reason": "stop"
+                    }]
+                }),
+            }),
+            Self::Malformed => {
+                ResponseTemplate::new(200).set_body_json(Self::answer(api, "I'd rather not say."))
+            }
+            Self::EmptyCandidates => ResponseTemplate::new(200).set_body_json(match api {
+                Api::Gemini => json!({ "candidates": [] }),
+                Api::OpenAi => json!({ "choices": [] }),
+            }),
+        }
+    }
+}
+
+// Plays the replies in order and keeps repeating the last one.
+struct Script {
+    api: Api,
+    replies: Vec<Reply>,
+    next: AtomicUsize,
+}
+
+impl Respond for Script {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        let body = String::from_utf8_lossy(&request.body);
+        let nonce = NONCE
+            .captures(&body)
+            .map(|c| c[1].to_string())
+            .unwrap_or_default();
+        let i = self.next.fetch_add(1, Ordering::Relaxed);
+        self.replies[i.min(self.replies.len() - 1)].respond(self.api, &nonce)
+    }
+}
+
+async fn serve(api: Api, path: &str, replies: Vec<Reply>) -> MockServer {
+    assert!(!replies.is_empty(), "a script needs at least one reply");
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path_regex(path))
+        .respond_with(Script {
+            api,
+            replies,
+            next: AtomicUsize::new(0),
+        })
+        .mount(&server)
+        .await;
+    server
+}
+
+async fn requests(server: &MockServer) -> usize {
+    server
+        .received_requests()
+        .await
+        .map(|r| r.len())
+        .unwrap_or_default()
+}
+
+pub struct MockGemini {
+    server: MockServer,
+}
+
+impl MockGemini {
+    pub async fn start(replies: Vec<Reply>) -> Self {
+        Self {
+            server: serve(Api::Gemini, r"^/models/[^/]+:generateContent$", replies).await,
+        }
+    }
+
+    pub fn client(&self) -> GeminiClient {
+        GeminiClient::new("key")
+            .base_url(self.server.uri())
+            .model("mock")
+    }
+
+    pub async fn requests(&self) -> usize {
+        requests(&self.server).await
+    }
+
+    pub async fn received(&self) -> Vec<Request> {
+        self.server.received_requests().await.unwrap_or_default()
+    }
+}
+
+pub struct MockOpenAi {
+    server: MockServer,
+}
+
+impl MockOpenAi {
+    pub async fn start(replies: Vec<Reply>) -> Self {
+        Self {
+            server: serve(Api::OpenAi, r"^/v1/chat/completions$", replies).await,
+        }
+    }
+
+    pub fn client(&self) -> OpenAiClient {
+        OpenAiClient::new("mock-local").base_url(format!("{}/v1", self.server.uri()))
+    }
+
+    pub async fn requests(&self) -> usize {
+        requests(&self.server).await
+    }
+
+    pub async fn received(&self) -> Vec<Request> {
+        self.server.received_requests().await.unwrap_or_default()
+    }
+}
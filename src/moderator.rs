@@ -0,0 +1,372 @@
+use serenity::all::{Message, Timestamp};
+use serenity::builder::{CreateEmbed, CreateMessage, EditMember};
+use serenity::model::Color;
+use serenity::prelude::Context;
+
+use crate::{
+    appeal::appeal_button,
+    config::{EscalationStep, GuildConfig, Mode, Punishment},
+    context::ContextEntry,
+    engine::{
+        AttachmentInput, Author, ModerationDecision, ModerationEngine, ModerationInput,
+        PlannedAction, ScoreError,
+    },
+    escalation::unix_now,
+    metrics::{incr, METRICS},
+    policy::Action,
+    review::review_buttons,
+    store::EscalationRecord,
+};
+
+fn truncate(content: &str) -> String {
+    content.char_indices().take(100).map(|f| f.1).collect()
+}
+
+fn generate_embed(
+    verb: &str,
+    msg: &Message,
+    input: &ModerationInput,
+    decision: &ModerationDecision,
+    review: Option<i64>,
+) -> CreateMessage {
+    let verdict = &decision.verdict;
+    let edited = match &input.previous {
+        Some(previous) => format!("***Edited from: *** ||{}||\n", truncate(previous)),
+        None => String::new(),
+    };
+    let providers = if verdict.provider_results.len() > 1 {
+        format!("\n***Providers: ***{}", verdict.provider_summary())
+    } else {
+        String::new()
+    };
+    let embed = CreateEmbed::default()
+        .title(format!(
+            "{}{}{}",
+            msg.author.tag(),
+            verb,
+            if input.edited { " (edit)" } else { "" }
+        ))
+        .color(Color::RED)
+        .description(format!(
+            ">>> {}***Message: *** :warning: ||{}||\n***Score: ***{}\n***Breakdown: ***{}\n***Triggered by: ***{}\n***AI Thoughts: ***{}{}",
+            edited,
+            truncate(&msg.content),
+            verdict.score,
+            verdict.breakdown(),
+            decision.trigger.as_deref().unwrap_or("overall score"),
+            verdict.reason,
+            providers
+        ));
+    let message = CreateMessage::new().embed(embed);
+    match review {
+        Some(id) => message.components(vec![review_buttons(id, decision.action)]),
+        None => message,
+    }
+}
+
+fn generate_escalation_embed(
+    user_tag: &str,
+    step: &EscalationStep,
+    before: f32,
+    after: f32,
+    error: Option<&serenity::Error>,
+) -> CreateMessage {
+    let verb = match step.action {
+        Punishment::Timeout => {
+            format!(" has been timed out for {} minutes!", step.duration_minutes)
+        }
+        Punishment::Kick => " has been kicked!".to_string(),
+        Punishment::Ban => " has been banned!".to_string(),
+    };
+    let mut description = format!(
+        ">>> ***Strikes: ***{:.1} -> {:.1}\n***Step: ***{} at {:.1} strikes",
+        before, after, step.action, step.strikes
+    );
+    if let Some(e) = error {
+        description.push_str(&format!("\n***Failed: ***{}", e));
+    }
+
+    let embed = CreateEmbed::default()
+        .title(format!("{}{}", user_tag, verb))
+        .color(if error.is_some() {
+            Color::ORANGE
+        } else {
+            Color::RED
+        })
+        .description(description);
+    CreateMessage::new().embed(embed)
+}
+
+pub fn context_entry(ctx: &Context, msg: &Message) -> ContextEntry {
+    ContextEntry {
+        id: msg.id,
+        author: msg.author.name.to_string(),
+        content: msg.content_safe(&ctx.cache).replace('\n', " "),
+        reply_to: msg.message_reference.as_ref().and_then(|r| r.message_id),
+    }
+}
+
+fn moderation_input(
+    ctx: &Context,
+    engine: &ModerationEngine,
+    msg: &Message,
+    previous: Option<&Message>,
+) -> ModerationInput {
+    // The cache only holds recent messages, so an edit to an older one comes
+    // without its earlier text. Discord still marks it as edited, and the
+    // store may have the text from when it was first scored.
+    let edited = previous.is_some() || msg.edited_timestamp.is_some();
+    let previous = match previous {
+        Some(previous) => Some(previous.content_safe(&ctx.cache)),
+        None if edited => engine.store.last_content(msg.id).unwrap_or_else(|e| {
+            log::error!("Failed to look up message {}: {}", msg.id, e);
+            None
+        }),
+        None => None,
+    };
+    let replied = msg
+        .referenced_message
+        .as_deref()
+        .map(|replied| context_entry(ctx, replied));
+    ModerationInput {
+        guild_id: msg.guild_id,
+        channel_id: msg.channel_id,
+        message_id: msg.id,
+        author: Author {
+            id: msg.author.id,
+            name: msg.author.name.to_string(),
+            avatar_url: msg.author.face(),
+        },
+        content: msg.content.to_string(),
+        clean_content: msg.content_safe(&ctx.cache),
+        edited,
+        previous,
+        reply_to: msg.message_reference.as_ref().and_then(|r| r.message_id),
+        attachments: msg
+            .attachments
+            .iter()
+            .map(|a| AttachmentInput {
+                filename: a.filename.to_string(),
+                url: a.url.to_string(),
+                size: a.size as u64,
+                content_type: a.content_type.clone(),
+            })
+            .collect(),
+        context: engine.context_for(msg.guild_id, msg.channel_id, msg.id, replied),
+    }
+}
+
+pub async fn moderate(
+    ctx: &Context,
+    engine: &ModerationEngine,
+    msg: &Message,
+    previous: Option<&Message>,
+) {
+    let input = moderation_input(ctx, engine, msg, previous);
+    match engine.evaluate(&input).await {
+        Ok(decision) => execute(ctx, engine, msg, &input, &decision).await,
+        Err(ScoreError::Provider(e)) if e.is_transient() => {
+            log::error!("Message {} left unmoderated: {}", msg.id, e);
+        }
+        Err(ScoreError::Provider(e)) => {
+            log::error!("Scoring request for message {} failed: {}", msg.id, e);
+        }
+        Err(ScoreError::Verdict(e)) => {
+            log::error!("Could not read verdict for message {}: {}", msg.id, e);
+            let channel = engine
+                .config
+                .current()
+                .for_guild(msg.guild_id)
+                .debug_log_channel;
+            if let Some(channel) = channel {
+                channel
+                    .say(
+                        ctx,
+                        format!(
+                            "\n```\n{}\n```\nNo verdict, message left unmoderated: {}",
+                            input.clean_content, e
+                        ),
+                    )
+                    .await
+                    .ok();
+            }
+        }
+    }
+}
+
+pub async fn prefilter(
+    ctx: &Context,
+    engine: &ModerationEngine,
+    msg: &Message,
+    previous: Option<&Message>,
+) {
+    let input = moderation_input(ctx, engine, msg, previous);
+    match engine.prefilter(&input) {
+        Some(decision) => {
+            incr(&METRICS.queue_prefiltered);
+            execute(ctx, engine, msg, &input, &decision).await;
+        }
+        None => log::debug!(
+            "Queue full, no pre-filter rule decided message {}, left unmoderated",
+            msg.id
+        ),
+    }
+}
+
+async fn execute(
+    ctx: &Context,
+    engine: &ModerationEngine,
+    msg: &Message,
+    input: &ModerationInput,
+    decision: &ModerationDecision,
+) {
+    let verdict = &decision.verdict;
+    for action in &decision.actions {
+        match action {
+            PlannedAction::LogDebug(channel) => {
+                channel
+                    .say(
+                        ctx,
+                        format!(
+                            "{}\n```\n{}\n```\nScore: {} ({}), Reason: {}\nProviders: {}{}",
+                            if input.edited { "(edit)" } else { "" },
+                            msg.content_safe(&ctx.cache),
+                            verdict.score,
+                            verdict.breakdown(),
+                            verdict.reason,
+                            verdict.provider_summary(),
+                            if decision.mode == Mode::Shadow {
+                                format!("\nShadow mode, would have taken: {}", decision.action)
+                            } else {
+                                String::new()
+                            }
+                        ),
+                    )
+                    .await
+                    .ok();
+            }
+            PlannedAction::Delete => {
+                msg.delete(ctx).await.ok();
+            }
+            PlannedAction::LogModeration { channel, review } => {
+                let verb = match decision.action {
+                    Action::Delete => "'s message has been deleted!",
+                    _ => "' has been warned!",
+                };
+                channel
+                    .send_message(ctx, generate_embed(verb, msg, input, decision, *review))
+                    .await
+                    .ok();
+            }
+            PlannedAction::Warn { appeal } => {
+                let dm = CreateMessage::new().content(format!(
+                    "Your {}message has been warned!\nYour message content: {}\nReason: {}",
+                    if input.edited { "edited " } else { "" },
+                    msg.content,
+                    verdict.reason
+                ));
+                let dm = match appeal {
+                    Some(id) => dm.components(vec![appeal_button(*id)]),
+                    None => dm,
+                };
+                msg.author.dm(ctx, dm).await.ok();
+            }
+            PlannedAction::Escalate(escalation) => {
+                let Some(guild_id) = msg.guild_id else {
+                    continue;
+                };
+                let current = engine.config.current();
+                let record = EscalationRecord {
+                    guild_id,
+                    user_id: msg.author.id,
+                    message_id: msg.id,
+                    step: &escalation.step,
+                    before: escalation.before,
+                    after: escalation.after,
+                    error: None,
+                };
+                punish(
+                    ctx,
+                    engine,
+                    current.for_guild(msg.guild_id),
+                    &msg.author.tag(),
+                    record,
+                )
+                .await;
+            }
+        }
+    }
+}
+
+pub async fn punish(
+    ctx: &Context,
+    engine: &ModerationEngine,
+    config: &GuildConfig,
+    user_tag: &str,
+    mut record: EscalationRecord<'_>,
+) {
+    let (guild_id, user_id, step) = (record.guild_id, record.user_id, record.step);
+    let reason = format!(
+        "{:.1} strikes after message {}",
+        record.after, record.message_id
+    );
+    let result = match step.action {
+        Punishment::Timeout => {
+            let until = (unix_now() + step.duration_minutes * 60) as i64;
+            match Timestamp::from_unix_timestamp(until) {
+                Ok(until) => guild_id
+                    .edit_member(
+                        ctx,
+                        user_id,
+                        EditMember::new()
+                            .disable_communication_until_datetime(until)
+                            .audit_log_reason(&reason),
+                    )
+                    .await
+                    .map(|_| ()),
+                Err(_) => return,
+            }
+        }
+        Punishment::Kick => guild_id.kick_with_reason(ctx, user_id, &reason).await,
+        Punishment::Ban => guild_id.ban_with_reason(ctx, user_id, 0, &reason).await,
+    };
+
+    match &result {
+        Ok(()) => log::info!(
+            "Escalated {} in guild {} to {} ({:.1} -> {:.1} strikes)",
+            user_id,
+            guild_id,
+            step.action,
+            record.before,
+            record.after
+        ),
+        Err(e) => log::error!(
+            "Failed to {} {} in guild {}: {}",
+            step.action,
+            user_id,
+            guild_id,
+            e
+        ),
+    }
+
+    record.error = result.as_ref().err().map(|e| e.to_string());
+    if let Err(e) = engine.store.record_escalation(&record) {
+        log::error!("Failed to record escalation for {}: {}", user_id, e);
+    }
+
+    if let Some(channel) = config.mod_log_channel {
+        channel
+            .send_message(
+                ctx,
+                generate_escalation_embed(
+                    user_tag,
+                    step,
+                    record.before,
+                    record.after,
+                    result.as_ref().err(),
+                ),
+            )
+            .await
+            .ok();
+    }
+}
@@ -0,0 +1,296 @@
+use std::{fmt, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    constants::DEFAULT_OPENAI_BASE_URL,
+    provider::{retry_after, Completion, ModerationProvider, Part, ProviderError, ScoreRequest},
+    ratelimit::RateLimiter,
+    verdict::ModerationVerdict,
+};
+
+#[derive(Debug, Serialize)]
+struct ChatRequest<'a> {
+    model: &'a str,
+    messages: Vec<ChatMessage>,
+    temperature: f32,
+    response_format: ResponseFormat,
+}
+
+#[derive(Debug, Serialize)]
+struct ResponseFormat {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    json_schema: JsonSchema,
+}
+
+#[derive(Debug, Serialize)]
+struct JsonSchema {
+    name: &'static str,
+    schema: serde_json::Value,
+}
+
+#[derive(Debug, Serialize)]
+struct ChatMessage {
+    role: &'static str,
+    content: ChatContent,
+}
+
+// Plain strings are the most widely supported form; the list form is only
+// used when there are images to send.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum ChatContent {
+    Text(String),
+    Parts(Vec<ChatPart>),
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ChatPart {
+    Text { text: String },
+    ImageUrl { image_url: ImageUrl },
+}
+
+#[derive(Debug, Serialize)]
+struct ImageUrl {
+    url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatResponse {
+    #[serde(default)]
+    choices: Vec<ChatChoice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatChoice {
+    message: ChatResponseMessage,
+    finish_reason: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChatResponseMessage {
+    content: Option<String>,
+    refusal: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: ErrorBody,
+}
+
+#[derive(Debug, Deserialize)]
+struct ErrorBody {
+    message: String,
+}
+
+#[derive(Debug)]
+pub enum OpenAiError {
+    Transport(reqwest::Error),
+    Status {
+        status: StatusCode,
+        message: Option<String>,
+        body: String,
+        retry_after: Option<Duration>,
+    },
+    Decode(serde_json::Error),
+    EmptyChoices,
+}
+
+impl fmt::Display for OpenAiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Transport(e) => write!(f, "transport error: {}", e),
+            Self::Status {
+                status,
+                message: Some(message),
+                ..
+            } => write!(f, "HTTP {}: {}", status, message),
+            Self::Status { status, body, .. } => write!(f, "HTTP {}: {}", status, body),
+            Self::Decode(e) => write!(f, "failed to decode response: {}", e),
+            Self::EmptyChoices => write!(f, "response contained no choices"),
+        }
+    }
+}
+
+impl std::error::Error for OpenAiError {}
+
+impl OpenAiError {
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::Transport(e) => e.is_timeout() || e.is_connect() || e.is_request(),
+            Self::Status { status, .. } => {
+                *status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+            }
+            _ => false,
+        }
+    }
+
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::Status { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for OpenAiError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Transport(e.without_url())
+    }
+}
+
+// Talks to anything that serves the OpenAI chat completions API: OpenAI
+// itself, or a local llama.cpp, vLLM or Ollama server.
+#[derive(Clone)]
+pub struct OpenAiClient {
+    http: reqwest::Client,
+    base_url: String,
+    model: String,
+    api_key: Option<String>,
+    limiter: Option<Arc<RateLimiter>>,
+}
+
+impl OpenAiClient {
+    pub fn new(model: impl Into<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: DEFAULT_OPENAI_BASE_URL.to_string(),
+            model: model.into(),
+            api_key: None,
+            limiter: None,
+        }
+    }
+
+    pub fn rate_limiter(mut self, limiter: Arc<RateLimiter>) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    pub fn base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    pub fn api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+
+    async fn chat(&self, body: &ChatRequest<'_>) -> Result<ChatResponse, OpenAiError> {
+        let mut req = self
+            .http
+            .post(format!("{}/chat/completions", self.base_url))
+            .json(body);
+        if let Some(api_key) = &self.api_key {
+            req = req.bearer_auth(api_key);
+        }
+        let res = req.send().await?;
+
+        let status = res.status();
+        let retry_after = retry_after(&res);
+        let text = res.text().await?;
+
+        if !status.is_success() {
+            return Err(OpenAiError::Status {
+                status,
+                retry_after,
+                message: serde_json::from_str::<ErrorResponse>(&text)
+                    .ok()
+                    .map(|e| e.error.message),
+                body: text,
+            });
+        }
+
+        serde_json::from_str(&text).map_err(OpenAiError::Decode)
+    }
+}
+
+#[async_trait]
+impl ModerationProvider for OpenAiClient {
+    fn model_name(&self) -> &str {
+        &self.model
+    }
+
+    fn rate_limiter(&self) -> Option<&RateLimiter> {
+        self.limiter.as_deref()
+    }
+
+    async fn complete(&self, request: &ScoreRequest) -> Result<Completion, ProviderError> {
+        let content = if request.parts.iter().all(|p| matches!(p, Part::Text(_))) {
+            ChatContent::Text(
+                request
+                    .parts
+                    .iter()
+                    .filter_map(|p| match p {
+                        Part::Text(text) => Some(text.as_str()),
+                        _ => None,
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n\n"),
+            )
+        } else {
+            ChatContent::Parts(
+                request
+                    .parts
+                    .iter()
+                    .map(|part| match part {
+                        Part::Text(text) => ChatPart::Text { text: text.clone() },
+                        Part::Image { mime_type, data } => ChatPart::ImageUrl {
+                            image_url: ImageUrl {
+                                url: format!("data:{};base64,{}", mime_type, data),
+                            },
+                        },
+                    })
+                    .collect(),
+            )
+        };
+        let body = ChatRequest {
+            model: &self.model,
+            messages: vec![
+                ChatMessage {
+                    role: "system",
+                    content: ChatContent::Text(request.system.clone()),
+                },
+                ChatMessage {
+                    role: "user",
+                    content,
+                },
+            ],
+            temperature: 0.0,
+            response_format: ResponseFormat {
+                kind: "json_schema",
+                json_schema: JsonSchema {
+                    name: "moderation_verdict",
+                    schema: ModerationVerdict::json_schema(),
+                },
+            },
+        };
+
+        let res = self.chat(&body).await.map_err(ProviderError::OpenAi)?;
+        let Some(choice) = res.choices.into_iter().next() else {
+            return Err(ProviderError::OpenAi(OpenAiError::EmptyChoices));
+        };
+
+        let text = choice.message.content.unwrap_or_default();
+        if text.is_empty() {
+            if let Some(refusal) = choice
+                .message
+                .refusal
+                .or(choice.finish_reason.filter(|r| r == "content_filter"))
+            {
+                return Err(ProviderError::Refused {
+                    reason: refusal,
+                    safety_ratings: vec![],
+                });
+            }
+        }
+        Ok(Completion {
+            text,
+            safety_ratings: vec![],
+        })
+    }
+}
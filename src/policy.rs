@@ -0,0 +1,255 @@
+use std::fmt;
+
+use crate::{
+    config::{GuildConfig, SafetyConfig},
+    defs::GeminiSafetyRating,
+    enums::{GeminiHarmCategory, GeminiHarmProbability},
+    verdict::{CategoryScore, ModerationVerdict},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Action {
+    None,
+    Warn,
+    Delete,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::None => write!(f, "none"),
+            Self::Warn => write!(f, "warn"),
+            Self::Delete => write!(f, "delete"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Decision {
+    pub action: Action,
+    pub trigger: Option<String>,
+}
+
+fn action_for(config: &GuildConfig, score: u16, delete: u16, warn: u16) -> Action {
+    if config.actions.delete && score >= delete {
+        Action::Delete
+    } else if config.actions.warn && score >= warn {
+        Action::Warn
+    } else {
+        Action::None
+    }
+}
+
+pub struct Scored<'a> {
+    pub category: Option<&'a str>,
+    pub score: u16,
+    pub delete: u16,
+    pub warn: u16,
+}
+
+// Every category score with the thresholds that apply to it. The overall
+// score is judged too when no category accounts for it, as happens when
+// safety ratings or injection signals add a lower category to a verdict
+// that had none.
+pub fn scored<'a>(config: &GuildConfig, verdict: &'a ModerationVerdict) -> Vec<Scored<'a>> {
+    let mut scores = verdict
+        .categories
+        .iter()
+        .map(|c| {
+            let (delete, warn) = config.thresholds(&c.category);
+            Scored {
+                category: Some(&c.category),
+                score: c.score,
+                delete,
+                warn,
+            }
+        })
+        .collect::<Vec<_>>();
+    if verdict.categories.iter().all(|c| c.score < verdict.score) {
+        scores.push(Scored {
+            category: None,
+            score: verdict.score,
+            delete: config.delete_threshold,
+            warn: config.warn_threshold,
+        });
+    }
+    scores
+}
+
+pub fn decide(config: &GuildConfig, verdict: &ModerationVerdict) -> Decision {
+    let mut decision = Decision {
+        action: Action::None,
+        trigger: None,
+    };
+    for s in scored(config, verdict) {
+        let action = action_for(config, s.score, s.delete, s.warn);
+        if action > decision.action {
+            decision = Decision {
+                action,
+                trigger: s.category.map(str::to_string),
+            };
+        }
+    }
+    decision
+}
+
+fn verdict_category(category: &GeminiHarmCategory) -> &'static str {
+    match category {
+        GeminiHarmCategory::Harassment | GeminiHarmCategory::Derogatory => "harassment",
+        GeminiHarmCategory::HateSpeech | GeminiHarmCategory::Toxicity => "hate_speech",
+        GeminiHarmCategory::SexuallyExplicit | GeminiHarmCategory::Sexual => "sexual",
+        GeminiHarmCategory::DangerousContent
+        | GeminiHarmCategory::Dangerous
+        | GeminiHarmCategory::Violence => "violence",
+        _ => "other",
+    }
+}
+
+fn safety_score(config: &SafetyConfig, probability: &GeminiHarmProbability) -> u16 {
+    match probability {
+        GeminiHarmProbability::Low => config.low_score,
+        GeminiHarmProbability::Medium => config.medium_score,
+        GeminiHarmProbability::High => config.high_score,
+        _ => 0,
+    }
+}
+
+pub fn apply_safety_ratings(
+    config: &SafetyConfig,
+    verdict: &mut ModerationVerdict,
+    ratings: &[GeminiSafetyRating],
+) {
+    if !config.enabled {
+        return;
+    }
+
+    let mut flagged = vec![];
+    for rating in ratings {
+        let score = safety_score(config, &rating.probability);
+        if score == 0 {
+            continue;
+        }
+
+        let category = verdict_category(&rating.category);
+        match verdict
+            .categories
+            .iter_mut()
+            .find(|c| c.category == category)
+        {
+            Some(c) => c.score = c.score.max(score),
+            None => verdict.categories.push(CategoryScore {
+                category: category.to_string(),
+                score,
+            }),
+        }
+        verdict.score = verdict.score.max(score);
+        flagged.push(format!("{} {}", rating.category, rating.probability));
+    }
+
+    if !flagged.is_empty() {
+        verdict.reason = format!(
+            "{} (safety ratings: {})",
+            verdict.reason,
+            flagged.join(", ")
+        )
+        .trim_start()
+        .to_string();
+    }
+}
+
+pub fn blocked_verdict(
+    config: &SafetyConfig,
+    reason: &str,
+    ratings: &[GeminiSafetyRating],
+) -> Option<ModerationVerdict> {
+    if !config.enabled {
+        return None;
+    }
+
+    let mut verdict = ModerationVerdict {
+        score: config.blocked_score,
+        categories: vec![],
+        reason: format!("The model refused to score this message ({})", reason),
+        rule_violated: None,
+        confidence: 1.0,
+        message_id: String::new(),
+        model: String::new(),
+        latency_ms: 0,
+        provider_results: vec![],
+        prefilter_rule: None,
+    };
+    apply_safety_ratings(config, &mut verdict, ratings);
+
+    if verdict.categories.is_empty() {
+        verdict.categories.push(CategoryScore {
+            category: "other".to_string(),
+            score: config.blocked_score,
+        });
+    }
+    Some(verdict)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn verdict(score: u16, categories: &[(&str, u16)]) -> ModerationVerdict {
+        ModerationVerdict {
+            score,
+            categories: categories
+                .iter()
+                .map(|(category, score)| CategoryScore {
+                    category: category.to_string(),
+                    score: *score,
+                })
+                .collect(),
+            reason: String::new(),
+            rule_violated: None,
+            confidence: 1.0,
+            message_id: String::new(),
+            model: String::new(),
+            latency_ms: 0,
+            provider_results: vec![],
+            prefilter_rule: None,
+        }
+    }
+
+    #[test]
+    fn category_thresholds_apply() {
+        let config = GuildConfig::default();
+        let decision = decide(&config, &verdict(700, &[("harassment", 700)]));
+        assert_eq!(decision.action, Action::Warn);
+        assert_eq!(decision.trigger.as_deref(), Some("harassment"));
+    }
+
+    #[test]
+    fn safety_ratings_do_not_downgrade_the_overall_score() {
+        let config = GuildConfig::default();
+        let mut v = verdict(900, &[]);
+        apply_safety_ratings(
+            &config.safety,
+            &mut v,
+            &[GeminiSafetyRating {
+                category: GeminiHarmCategory::Harassment,
+                probability: GeminiHarmProbability::Medium,
+                blocked: None,
+            }],
+        );
+        assert_eq!(v.categories.len(), 1);
+        let decision = decide(&config, &v);
+        assert_eq!(decision.action, Action::Delete);
+        assert_eq!(decision.trigger, None);
+    }
+
+    #[test]
+    fn lenient_category_thresholds_still_hold() {
+        let mut config = GuildConfig::default();
+        config
+            .categories
+            .entry("harassment".to_string())
+            .or_default()
+            .delete_threshold = Some(950);
+        let decision = decide(&config, &verdict(900, &[("harassment", 900)]));
+        assert_eq!(decision.action, Action::Warn);
+    }
+}
@@ -0,0 +1,271 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+use crate::config::PrefilterConfig;
+
+static CUSTOM_EMOJI: Lazy<Regex> = Lazy::new(|| Regex::new(r"<a?:\w+:\d+>").unwrap());
+
+// A block_patterns entry, compiled when the config is loaded so that an
+// invalid one is rejected there and reloads drop the old ones.
+#[derive(Debug, Clone)]
+pub struct BlockPattern(Regex);
+
+impl BlockPattern {
+    pub fn new(pattern: &str) -> Result<Self, regex::Error> {
+        Regex::new(pattern).map(Self)
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl PartialEq for BlockPattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Serialize for BlockPattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for BlockPattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        Self::new(&pattern).map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrefilterMatch {
+    pub rule: String,
+    pub reason: String,
+    pub blocked: bool,
+}
+
+impl PrefilterMatch {
+    fn clean(rule: &str, reason: impl Into<String>) -> Self {
+        Self {
+            rule: rule.to_string(),
+            reason: reason.into(),
+            blocked: false,
+        }
+    }
+
+    fn blocked(rule: &str, entry: &str, reason: impl Into<String>) -> Self {
+        Self {
+            rule: format!("{}: {}", rule, entry),
+            reason: reason.into(),
+            blocked: true,
+        }
+    }
+}
+
+fn is_invisible(c: char) -> bool {
+    matches!(
+        c,
+        '\u{00ad}' | '\u{034f}' | '\u{180e}' | '\u{200b}'..='\u{200f}' | '\u{2060}'..='\u{2064}' | '\u{feff}'
+    )
+}
+
+// NFKC folds full-width and stylised letters into plain ones. Accents are
+// dropped from Latin letters only, so kana keep their voicing marks.
+pub fn normalize(text: &str) -> String {
+    let mut base = ' ';
+    let stripped = text
+        .nfkd()
+        .filter(|c| !is_invisible(*c))
+        .filter(|c| {
+            if is_combining_mark(*c) {
+                !base.is_ascii()
+            } else {
+                base = *c;
+                true
+            }
+        })
+        .collect::<String>();
+    stripped
+        .nfkc()
+        .collect::<String>()
+        .to_lowercase()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn compact(normalized: &str) -> String {
+    normalized.chars().filter(|c| c.is_alphanumeric()).collect()
+}
+
+// Punctuation inside a word is dropped so that "i.d.i.o.t" still matches,
+// but words are kept apart so that entries do not match across them.
+fn words(normalized: &str) -> String {
+    normalized
+        .split_whitespace()
+        .map(compact)
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Kana, kanji and Thai are written without spaces between words.
+fn is_unspaced(c: char) -> bool {
+    matches!(
+        c,
+        '\u{0e00}'..='\u{0e7f}' | '\u{3040}'..='\u{30ff}' | '\u{3400}'..='\u{4dbf}' | '\u{4e00}'..='\u{9fff}' | '\u{f900}'..='\u{faff}'
+    )
+}
+
+fn at_boundary(neighbour: Option<char>, edge: Option<char>) -> bool {
+    match (neighbour, edge) {
+        (Some(n), Some(e)) => n == ' ' || is_unspaced(n) || is_unspaced(e),
+        _ => true,
+    }
+}
+
+// Entries match whole words only, so that "ass" does not block "class". In
+// scripts without spaces there is no boundary to check and they match anywhere.
+fn contains_word(haystack: &str, needle: &str) -> bool {
+    haystack.match_indices(needle).any(|(i, _)| {
+        at_boundary(haystack[..i].chars().next_back(), needle.chars().next())
+            && at_boundary(
+                haystack[i + needle.len()..].chars().next(),
+                needle.chars().next_back(),
+            )
+    })
+}
+
+// Blocklists are checked first so that nothing trivial-looking slips past
+// them. The clean shortcuts only apply to messages without attachments.
+pub fn check(
+    config: &PrefilterConfig,
+    content: &str,
+    has_attachments: bool,
+) -> Option<PrefilterMatch> {
+    let normalized = normalize(content);
+    let haystack = words(&normalized);
+
+    for word in &config.block_words {
+        let needle = words(&normalize(word));
+        if !needle.is_empty() && contains_word(&haystack, &needle) {
+            return Some(PrefilterMatch::blocked(
+                "block_words",
+                word,
+                format!("matched blocked word \"{}\"", word),
+            ));
+        }
+    }
+    for pattern in &config.block_patterns {
+        if pattern.0.is_match(&normalized) {
+            return Some(PrefilterMatch::blocked(
+                "block_patterns",
+                pattern.as_str(),
+                format!("matched blocked pattern `{}`", pattern.as_str()),
+            ));
+        }
+    }
+
+    if has_attachments {
+        return None;
+    }
+    let length = compact(&CUSTOM_EMOJI.replace_all(&normalized, " "))
+        .chars()
+        .count();
+    if length < config.min_length {
+        return Some(PrefilterMatch::clean(
+            "min_length",
+            format!(
+                "{} letter(s) or digit(s), below the minimum of {}",
+                length, config.min_length
+            ),
+        ));
+    }
+    if config.allow.iter().any(|a| normalize(a) == normalized) {
+        return Some(PrefilterMatch::clean("allow", "on the allowlist"));
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GuildConfig;
+
+    fn config() -> PrefilterConfig {
+        PrefilterConfig {
+            min_length: 2,
+            block_words: vec!["idiot".to_string()],
+            block_patterns: vec![BlockPattern::new(r"discord\.gg/\w+").unwrap()],
+            allow: vec!["lol".to_string(), "草".to_string()],
+            ..GuildConfig::default().prefilter
+        }
+    }
+
+    #[test]
+    fn disguised_words_are_blocked() {
+        for text in [
+            "you IDIOT",
+            "ｉｄｉｏｔ",
+            "i.d.i.o.t",
+            "ídíót",
+            "id\u{200b}iot",
+            "𝐢𝐝𝐢𝐨𝐭",
+        ] {
+            let m = check(&config(), text, false).expect(text);
+            assert!(m.blocked, "{}", text);
+            assert_eq!(m.rule, "block_words: idiot");
+        }
+        assert_eq!(check(&config(), "i said i o take it", false), None);
+    }
+
+    #[test]
+    fn words_match_whole_words_only() {
+        assert_eq!(check(&config(), "an idiotic plan", false), None);
+        assert_eq!(check(&config(), "presidiot", false), None);
+        let config = PrefilterConfig {
+            block_words: vec!["ばか".to_string()],
+            ..config()
+        };
+        assert!(check(&config, "お前ばかだな", false).unwrap().blocked);
+    }
+
+    #[test]
+    fn patterns_see_normalized_text() {
+        let m = check(&config(), "join DISCORD.GG/abc", false).unwrap();
+        assert!(m.blocked);
+        assert_eq!(m.rule, r"block_patterns: discord\.gg/\w+");
+    }
+
+    #[test]
+    fn trivial_messages_are_clean() {
+        for text in [
+            "LOL",
+            " lol ",
+            "草",
+            "👍👍",
+            "<:pepe:123456789012345678>",
+            "a",
+        ] {
+            let m = check(&config(), text, false).expect(text);
+            assert!(!m.blocked, "{}", text);
+        }
+    }
+
+    #[test]
+    fn attachments_are_never_skipped() {
+        assert_eq!(check(&config(), "lol", true), None);
+        assert!(check(&config(), "idiot", true).unwrap().blocked);
+    }
+
+    #[test]
+    fn kana_keep_their_voicing_marks() {
+        assert_eq!(normalize("ガギグ"), "ガギグ");
+        assert_eq!(normalize("ｶﾞ"), "ガ");
+        assert_eq!(check(&config(), "ばか", false), None);
+    }
+}
@@ -0,0 +1,142 @@
+use std::{collections::BTreeSet, fs, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{config::ConfigError, verdict::CATEGORIES};
+
+pub static PLACEHOLDERS: [&str; 4] = ["rules", "examples", "language", "categories"];
+pub static REQUIRED_PLACEHOLDERS: [&str; 1] = ["rules"];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PromptExample {
+    pub message: String,
+    pub verdict: String,
+}
+
+pub struct PromptVars<'a> {
+    pub rules: &'a [String],
+    pub examples: &'a [PromptExample],
+    pub language: &'a str,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Text(String),
+    Placeholder(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PromptTemplate {
+    segments: Vec<Segment>,
+    source: String,
+}
+
+impl PromptTemplate {
+    pub fn load(path: &Path) -> Result<Self, ConfigError> {
+        let text = fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_path_buf(), e))?;
+        Self::parse(&text)
+            .map_err(|e| ConfigError::Invalid(format!("prompt {}: {}", path.display(), e)))
+    }
+
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut segments = vec![];
+        let mut literal = String::new();
+        let mut chars = text.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => return Err(format!("unclosed placeholder {{{}", name)),
+                        }
+                    }
+                    if name == "content" {
+                        return Err(
+                            "{content} is no longer supported, the message is sent as its own turn"
+                                .to_string(),
+                        );
+                    }
+                    if !PLACEHOLDERS.contains(&name.as_str()) {
+                        return Err(format!(
+                            "unknown placeholder {{{}}}, expected one of {:?} (use {{{{ and }}}} for literal braces)",
+                            name, PLACEHOLDERS
+                        ));
+                    }
+                    segments.push(Segment::Text(std::mem::take(&mut literal)));
+                    segments.push(Segment::Placeholder(name));
+                }
+                '}' => return Err("unmatched }, use }} for a literal brace".to_string()),
+                c => literal.push(c),
+            }
+        }
+        segments.push(Segment::Text(literal));
+
+        let used = segments
+            .iter()
+            .filter_map(|s| match s {
+                Segment::Placeholder(name) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect::<BTreeSet<_>>();
+        if let Some(missing) = REQUIRED_PLACEHOLDERS.iter().find(|p| !used.contains(*p)) {
+            return Err(format!("missing required placeholder {{{}}}", missing));
+        }
+
+        Ok(Self {
+            segments,
+            source: text.to_string(),
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn asks_for_message_id(&self) -> bool {
+        self.source.contains("message_id")
+    }
+
+    pub fn render(&self, vars: &PromptVars) -> String {
+        let mut out = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => out.push_str(text),
+                Segment::Placeholder(name) => match name.as_str() {
+                    "rules" => out.push_str(
+                        &vars
+                            .rules
+                            .iter()
+                            .map(|rule| format!("- {}", rule))
+                            .collect::<Vec<_>>()
+                            .join("\n"),
+                    ),
+                    "examples" => out.push_str(
+                        &vars
+                            .examples
+                            .iter()
+                            .map(|e| format!("Example for {:?}: {}", e.message, e.verdict))
+                            .collect::<Vec<_>>()
+                            .join("\n"),
+                    ),
+                    "language" => out.push_str(vars.language),
+                    "categories" => out.push_str(&CATEGORIES.join(", ")),
+                    _ => unreachable!("placeholders are checked in parse"),
+                },
+            }
+        }
+        out
+    }
+}
@@ -0,0 +1,250 @@
+use std::{
+    collections::HashMap,
+    env, fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use rand::Rng;
+use reqwest::header::RETRY_AFTER;
+
+use crate::{
+    config::{ProviderConfig, ProviderKind, RateLimitConfig, RetryConfig},
+    defs::GeminiSafetyRating,
+    gemini::{GeminiClient, GeminiError},
+    metrics::{incr, METRICS},
+    openai::{OpenAiClient, OpenAiError},
+    ratelimit::RateLimiter,
+};
+
+#[derive(Debug, Clone)]
+pub enum Part {
+    Text(String),
+    Image { mime_type: String, data: String },
+}
+
+#[derive(Debug, Clone)]
+pub struct ScoreRequest {
+    pub system: String,
+    pub parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Completion {
+    pub text: String,
+    pub safety_ratings: Vec<GeminiSafetyRating>,
+}
+
+#[derive(Debug)]
+pub enum ProviderError {
+    Gemini(GeminiError),
+    OpenAi(OpenAiError),
+    Timeout,
+    RateLimited(Duration),
+    Refused {
+        reason: String,
+        safety_ratings: Vec<GeminiSafetyRating>,
+    },
+    Unavailable(String),
+}
+
+impl fmt::Display for ProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Gemini(e) => write!(f, "Gemini: {}", e),
+            Self::OpenAi(e) => write!(f, "OpenAI-compatible API: {}", e),
+            Self::Timeout => write!(f, "retry budget exhausted before a response arrived"),
+            Self::RateLimited(wait) => write!(
+                f,
+                "client-side rate limit reached, next request allowed in {:?}",
+                wait
+            ),
+            Self::Refused { reason, .. } => write!(f, "model refused to answer ({})", reason),
+            Self::Unavailable(name) => write!(
+                f,
+                "provider {:?} was not loaded at startup, restart to use it",
+                name
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ProviderError {}
+
+impl ProviderError {
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::Gemini(e) => e.is_transient(),
+            Self::OpenAi(e) => e.is_transient(),
+            Self::Timeout | Self::RateLimited(_) => true,
+            _ => false,
+        }
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::Gemini(e) => e.retry_after(),
+            Self::OpenAi(e) => e.retry_after(),
+            _ => None,
+        }
+    }
+}
+
+pub fn retry_after(res: &reqwest::Response) -> Option<Duration> {
+    res.headers()
+        .get(RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+}
+
+#[async_trait]
+pub trait ModerationProvider: Send + Sync {
+    fn model_name(&self) -> &str;
+
+    fn rate_limiter(&self) -> Option<&RateLimiter>;
+
+    async fn complete(&self, request: &ScoreRequest) -> Result<Completion, ProviderError>;
+
+    async fn complete_with_retry(
+        &self,
+        request: &ScoreRequest,
+        policy: &RetryConfig,
+    ) -> Result<Completion, ProviderError> {
+        let started = Instant::now();
+        let budget = Duration::from_millis(policy.max_total_ms);
+        let mut backoff = Duration::from_millis(policy.initial_backoff_ms);
+        let mut attempt = 1;
+
+        loop {
+            // A rate limit that outlasts the budget fails straight away, so
+            // the next provider in the chain can answer instead.
+            if let Some(limiter) = self.rate_limiter() {
+                let remaining = budget.saturating_sub(started.elapsed());
+                if let Err(wait) = limiter.acquire(remaining).await {
+                    incr(&METRICS.provider_give_ups);
+                    let e = ProviderError::RateLimited(wait);
+                    log::warn!("Giving up on {} request: {}", self.model_name(), e);
+                    return Err(e);
+                }
+            }
+            incr(&METRICS.provider_requests);
+
+            let remaining = budget.saturating_sub(started.elapsed());
+            let res = match tokio::time::timeout(remaining, self.complete(request)).await {
+                Ok(res) => res,
+                Err(_) => Err(ProviderError::Timeout),
+            };
+
+            let e = match res {
+                Ok(res) => return Ok(res),
+                Err(e) if !e.is_transient() => return Err(e),
+                Err(e) => e,
+            };
+
+            let jittered = rand::thread_rng().gen_range(Duration::ZERO..=backoff);
+            let delay = e.retry_after().unwrap_or(jittered);
+
+            if attempt >= policy.max_attempts || started.elapsed() + delay >= budget {
+                incr(&METRICS.provider_give_ups);
+                log::warn!(
+                    "Giving up on {} request after {} attempt(s) in {:?}: {}",
+                    self.model_name(),
+                    attempt,
+                    started.elapsed(),
+                    e
+                );
+                return Err(e);
+            }
+
+            incr(&METRICS.provider_retries);
+            log::debug!(
+                "{} attempt {} failed ({}), retrying in {:?}",
+                self.model_name(),
+                attempt,
+                e,
+                delay
+            );
+            tokio::time::sleep(delay).await;
+
+            backoff = (backoff * 2).min(Duration::from_millis(policy.max_backoff_ms));
+            attempt += 1;
+        }
+    }
+}
+
+fn build(
+    config: &ProviderConfig,
+    rate_limit: &RateLimitConfig,
+) -> Result<Arc<dyn ModerationProvider>, String> {
+    let api_key = match &config.api_key_env {
+        Some(name) => Some(env::var(name).map_err(|_| format!("{} is not set", name))?),
+        None => None,
+    };
+    let limiter = Arc::new(RateLimiter::new(rate_limit));
+
+    Ok(match config.kind {
+        ProviderKind::Gemini => {
+            let api_key = api_key
+                .or_else(|| env::var("GEMINI_API_KEY").ok())
+                .ok_or("GEMINI_API_KEY is not set")?;
+            let mut client = GeminiClient::new(api_key).rate_limiter(limiter);
+            if let Some(base_url) = config
+                .base_url
+                .clone()
+                .or_else(|| env::var("GEMINI_BASE_URL").ok())
+            {
+                client = client.base_url(base_url);
+            }
+            if let Some(model) = config
+                .model
+                .clone()
+                .or_else(|| env::var("GEMINI_MODEL").ok())
+            {
+                client = client.model(model);
+            }
+            Arc::new(client)
+        }
+        ProviderKind::OpenAi => {
+            let mut client =
+                OpenAiClient::new(config.model.clone().unwrap_or_default()).rate_limiter(limiter);
+            if let Some(base_url) = &config.base_url {
+                client = client.base_url(base_url);
+            }
+            if let Some(api_key) = api_key {
+                client = client.api_key(api_key);
+            }
+            Arc::new(client)
+        }
+    })
+}
+
+// Every configured provider gets its own client and rate limiter. A provider
+// no guild uses may fail to load (a missing API key, say) without stopping
+// the bot.
+pub fn build_providers(
+    providers: &HashMap<String, ProviderConfig>,
+    used: &[&str],
+    rate_limit: &RateLimitConfig,
+) -> Result<HashMap<String, Arc<dyn ModerationProvider>>, String> {
+    let mut built = HashMap::new();
+    for (name, config) in providers {
+        match build(config, rate_limit) {
+            Ok(provider) => {
+                log::info!(
+                    "Loaded provider {} ({}, {})",
+                    name,
+                    config.kind,
+                    provider.model_name()
+                );
+                built.insert(name.clone(), provider);
+            }
+            Err(e) if used.contains(&name.as_str()) => {
+                return Err(format!("provider {}: {}", name, e));
+            }
+            Err(e) => log::warn!("Skipping unused provider {}: {}", name, e),
+        }
+    }
+    Ok(built)
+}
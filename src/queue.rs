@@ -0,0 +1,151 @@
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    sync::{
+        atomic::{self, AtomicU64},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use serenity::all::{Message, Timestamp};
+use serenity::prelude::Context;
+use tokio::sync::Notify;
+
+use crate::metrics::METRICS;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Priority {
+    Normal,
+    NewAccount,
+}
+
+impl Priority {
+    pub fn of(msg: &Message, new_account_days: u64) -> Self {
+        let age = Timestamp::now().unix_timestamp() - msg.author.id.created_at().unix_timestamp();
+        if age < (new_account_days * 60 * 60 * 24) as i64 {
+            Self::NewAccount
+        } else {
+            Self::Normal
+        }
+    }
+}
+
+pub struct Job {
+    pub ctx: Context,
+    pub msg: Message,
+    pub previous: Option<Message>,
+    priority: Priority,
+    seq: u64,
+}
+
+impl PartialEq for Job {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Job {}
+
+impl PartialOrd for Job {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Job {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority
+            .cmp(&other.priority)
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+pub struct ModerationQueue {
+    jobs: Mutex<BinaryHeap<Job>>,
+    capacity: usize,
+    seq: AtomicU64,
+    pushed: Notify,
+    popped: Notify,
+}
+
+impl ModerationQueue {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            jobs: Mutex::new(BinaryHeap::with_capacity(capacity)),
+            capacity,
+            seq: AtomicU64::new(0),
+            pushed: Notify::new(),
+            popped: Notify::new(),
+        }
+    }
+
+    pub fn try_push(
+        &self,
+        ctx: Context,
+        msg: Message,
+        previous: Option<Message>,
+        priority: Priority,
+    ) -> Result<(), Job> {
+        let job = Job {
+            ctx,
+            msg,
+            previous,
+            priority,
+            seq: self.seq.fetch_add(1, atomic::Ordering::Relaxed),
+        };
+        self.push_job(job)
+    }
+
+    fn push_job(&self, job: Job) -> Result<(), Job> {
+        let mut jobs = self.jobs.lock().unwrap();
+        if jobs.len() >= self.capacity {
+            return Err(job);
+        }
+        jobs.push(job);
+        METRICS
+            .queue_depth
+            .store(jobs.len() as u64, atomic::Ordering::Relaxed);
+        drop(jobs);
+
+        self.pushed.notify_one();
+        Ok(())
+    }
+
+    pub async fn push_timeout(&self, job: Job, timeout: Duration) -> Result<(), Job> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        let mut job = job;
+        loop {
+            let popped = self.popped.notified();
+            job = match self.push_job(job) {
+                Ok(()) => return Ok(()),
+                Err(job) => job,
+            };
+            if tokio::time::timeout_at(deadline, popped).await.is_err() {
+                return Err(job);
+            }
+        }
+    }
+
+    pub async fn pop(&self) -> Job {
+        loop {
+            let pushed = self.pushed.notified();
+            if let Some(job) = self.pop_now() {
+                return job;
+            }
+            pushed.await;
+        }
+    }
+
+    fn pop_now(&self) -> Option<Job> {
+        let mut jobs = self.jobs.lock().unwrap();
+        let job = jobs.pop()?;
+        METRICS
+            .queue_depth
+            .store(jobs.len() as u64, atomic::Ordering::Relaxed);
+        drop(jobs);
+
+        self.popped.notify_one();
+        Some(job)
+    }
+}
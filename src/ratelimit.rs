@@ -0,0 +1,89 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::config::RateLimitConfig;
+
+struct Bucket {
+    capacity: f64,
+    per_second: f64,
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl Bucket {
+    fn new(capacity: u32, period: Duration) -> Self {
+        Self {
+            capacity: capacity as f64,
+            per_second: capacity as f64 / period.as_secs_f64(),
+            tokens: capacity as f64,
+            refilled: Instant::now(),
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.per_second).min(self.capacity);
+        self.refilled = now;
+    }
+
+    fn wait_time(&self) -> Duration {
+        if self.tokens >= 1.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((1.0 - self.tokens) / self.per_second)
+        }
+    }
+}
+
+pub struct RateLimiter {
+    buckets: Mutex<[Bucket; 2]>,
+}
+
+impl RateLimiter {
+    pub fn new(config: &RateLimitConfig) -> Self {
+        Self {
+            buckets: Mutex::new([
+                Bucket::new(config.rpm, Duration::from_secs(60)),
+                Bucket::new(config.rpd, Duration::from_secs(60 * 60 * 24)),
+            ]),
+        }
+    }
+
+    // Takes a token, or returns how long until one is available.
+    pub fn try_acquire(&self) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap();
+        let now = Instant::now();
+        buckets.iter_mut().for_each(|b| b.refill(now));
+
+        let wait = buckets
+            .iter()
+            .map(Bucket::wait_time)
+            .max()
+            .unwrap_or_default();
+        if wait.is_zero() {
+            buckets.iter_mut().for_each(|b| b.tokens -= 1.0);
+            return Ok(());
+        }
+        Err(wait)
+    }
+
+    // Waits at most `limit` for a token. When the next one is further away,
+    // returns how long it would have taken instead.
+    pub async fn acquire(&self, limit: Duration) -> Result<(), Duration> {
+        let deadline = Instant::now() + limit;
+        loop {
+            let wait = match self.try_acquire() {
+                Ok(()) => return Ok(()),
+                Err(wait) => wait,
+            };
+            if Instant::now() + wait > deadline {
+                return Err(wait);
+            }
+
+            log::debug!("Rate limit reached, waiting {:?}", wait);
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
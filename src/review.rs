@@ -0,0 +1,303 @@
+use serenity::all::{
+    ButtonStyle, ComponentInteraction, CreateActionRow, CreateAllowedMentions, CreateButton,
+    CreateInteractionResponse, CreateInteractionResponseMessage, CreateWebhook, ExecuteWebhook,
+    Permissions,
+};
+use serenity::prelude::Context;
+
+use crate::{
+    config::GuildConfig,
+    engine::ModerationEngine,
+    escalation::crossed_step,
+    moderator::punish,
+    policy::Action,
+    store::{EscalationRecord, Review},
+};
+
+static WEBHOOK_NAME: &str = "Gemini Moderator";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Confirmed,
+    Restored,
+    Escalated,
+    Dismissed,
+}
+
+impl Outcome {
+    fn id(self) -> &'static str {
+        match self {
+            Self::Confirmed => "confirm",
+            Self::Restored => "restore",
+            Self::Escalated => "escalate",
+            Self::Dismissed => "dismiss",
+        }
+    }
+
+    fn from_id(id: &str) -> Option<Self> {
+        [
+            Self::Confirmed,
+            Self::Restored,
+            Self::Escalated,
+            Self::Dismissed,
+        ]
+        .into_iter()
+        .find(|o| o.id() == id)
+    }
+
+    fn stored(self) -> &'static str {
+        match self {
+            Self::Confirmed => "confirmed",
+            Self::Restored => "restored",
+            Self::Escalated => "escalated",
+            Self::Dismissed => "dismissed",
+        }
+    }
+
+    fn button(self, decision_id: i64) -> CreateButton {
+        let (label, style) = match self {
+            Self::Confirmed => ("Confirm", ButtonStyle::Success),
+            Self::Restored => ("Restore", ButtonStyle::Secondary),
+            Self::Escalated => ("Escalate", ButtonStyle::Danger),
+            Self::Dismissed => ("Dismiss warning", ButtonStyle::Secondary),
+        };
+        CreateButton::new(format!("review:{}:{}", decision_id, self.id()))
+            .label(label)
+            .style(style)
+    }
+}
+
+pub fn review_buttons(decision_id: i64, action: Action) -> CreateActionRow {
+    let outcomes = match action {
+        Action::Delete => [Outcome::Confirmed, Outcome::Restored, Outcome::Escalated],
+        _ => [Outcome::Confirmed, Outcome::Escalated, Outcome::Dismissed],
+    };
+    CreateActionRow::Buttons(outcomes.iter().map(|o| o.button(decision_id)).collect())
+}
+
+fn parse_custom_id(custom_id: &str) -> Option<(i64, Outcome)> {
+    let mut parts = custom_id.strip_prefix("review:")?.splitn(2, ':');
+    let decision_id = parts.next()?.parse().ok()?;
+    let outcome = Outcome::from_id(parts.next()?)?;
+    Some((decision_id, outcome))
+}
+
+async fn restore(ctx: &Context, review: &Review) -> Result<(), String> {
+    let Some(content) = review.content.as_deref().filter(|c| !c.is_empty()) else {
+        return Err("the message content was not kept".to_string());
+    };
+
+    let webhooks = review
+        .channel_id
+        .webhooks(ctx)
+        .await
+        .map_err(|e| e.to_string())?;
+    let webhook = match webhooks
+        .into_iter()
+        .find(|w| w.name.as_deref() == Some(WEBHOOK_NAME) && w.token.is_some())
+    {
+        Some(webhook) => webhook,
+        None => review
+            .channel_id
+            .create_webhook(ctx, CreateWebhook::new(WEBHOOK_NAME))
+            .await
+            .map_err(|e| e.to_string())?,
+    };
+
+    webhook
+        .execute(
+            ctx,
+            false,
+            ExecuteWebhook::new()
+                .content(content)
+                .username(&review.author_name)
+                .avatar_url(&review.author_avatar)
+                .allowed_mentions(CreateAllowedMentions::new()),
+        )
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+fn remove_strikes(
+    engine: &ModerationEngine,
+    config: &GuildConfig,
+    review: &Review,
+) -> Result<(), String> {
+    if review.strikes <= 0.0 {
+        return Ok(());
+    }
+    engine
+        .store
+        .add_strikes(
+            review.guild_id,
+            review.author_id,
+            -review.strikes,
+            config.escalation.half_life_hours,
+        )
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+async fn escalate(
+    ctx: &Context,
+    engine: &ModerationEngine,
+    config: &GuildConfig,
+    review: &Review,
+) -> Result<(), String> {
+    let escalation = &config.escalation;
+    let current = engine
+        .store
+        .strikes(
+            review.guild_id,
+            review.author_id,
+            escalation.half_life_hours,
+        )
+        .map_err(|e| e.to_string())?;
+    let Some(next) = escalation.steps.iter().find(|s| s.strikes > current) else {
+        return Err("the user is already past the last escalation step".to_string());
+    };
+
+    let (before, after) = engine
+        .store
+        .add_strikes(
+            review.guild_id,
+            review.author_id,
+            next.strikes - current,
+            escalation.half_life_hours,
+        )
+        .map_err(|e| e.to_string())?;
+    let step = crossed_step(escalation, before, after).unwrap_or(next);
+
+    let record = EscalationRecord {
+        guild_id: review.guild_id,
+        user_id: review.author_id,
+        message_id: review.message_id,
+        step,
+        before,
+        after,
+        error: None,
+    };
+    punish(ctx, engine, config, &review.author_name, record).await;
+    Ok(())
+}
+
+async fn reply(ctx: &Context, interaction: &ComponentInteraction, content: String) {
+    let response = CreateInteractionResponse::Message(
+        CreateInteractionResponseMessage::new()
+            .content(content)
+            .ephemeral(true),
+    );
+    if let Err(e) = interaction.create_response(ctx, response).await {
+        log::error!("Failed to respond to a review button: {}", e);
+    }
+}
+
+pub async fn handle(ctx: &Context, interaction: &ComponentInteraction, engine: &ModerationEngine) {
+    let Some((decision_id, outcome)) = parse_custom_id(&interaction.data.custom_id) else {
+        return;
+    };
+    let Some(guild_id) = interaction.guild_id else {
+        return;
+    };
+
+    let allowed = interaction
+        .member
+        .as_ref()
+        .and_then(|m| m.permissions)
+        .is_some_and(|p| p.contains(Permissions::MODERATE_MEMBERS));
+    if !allowed {
+        reply(
+            ctx,
+            interaction,
+            format!(
+                "You need the {} permission to review decisions.",
+                Permissions::MODERATE_MEMBERS
+            ),
+        )
+        .await;
+        return;
+    }
+
+    let resolved = engine
+        .store
+        .resolve_review(decision_id, outcome.stored(), interaction.user.id);
+    let review = match resolved {
+        Ok(Some(review)) => review,
+        Ok(None) => {
+            reply(
+                ctx,
+                interaction,
+                "This decision was already reviewed.".to_string(),
+            )
+            .await;
+            return;
+        }
+        Err(e) => {
+            log::error!("Failed to resolve review {}: {}", decision_id, e);
+            reply(
+                ctx,
+                interaction,
+                format!("Could not resolve the review: {}", e),
+            )
+            .await;
+            return;
+        }
+    };
+
+    let current = engine.config.current();
+    let config = current.for_guild(Some(guild_id));
+    let result = match outcome {
+        Outcome::Confirmed => Ok(()),
+        Outcome::Restored => match restore(ctx, &review).await {
+            Ok(()) => remove_strikes(engine, config, &review),
+            Err(e) => Err(e),
+        },
+        Outcome::Escalated => escalate(ctx, engine, config, &review).await,
+        Outcome::Dismissed => remove_strikes(engine, config, &review),
+    };
+
+    if let Err(e) = result {
+        log::error!(
+            "Failed to apply review {} ({}): {}",
+            decision_id,
+            outcome.stored(),
+            e
+        );
+        if let Err(e) = engine.store.reopen_review(&review) {
+            log::error!("Failed to reopen review {}: {}", decision_id, e);
+        }
+        reply(
+            ctx,
+            interaction,
+            format!("Could not {}: {}", outcome.id(), e),
+        )
+        .await;
+        return;
+    }
+
+    log::info!(
+        "Decision {} in guild {} {} by {}",
+        decision_id,
+        guild_id,
+        outcome.stored(),
+        interaction.user.id
+    );
+    let response = CreateInteractionResponse::UpdateMessage(
+        CreateInteractionResponseMessage::new()
+            .content(format!(
+                "{} by <@{}>",
+                match outcome {
+                    Outcome::Confirmed => "Confirmed",
+                    Outcome::Restored => "Restored",
+                    Outcome::Escalated => "Escalated",
+                    Outcome::Dismissed => "Warning dismissed",
+                },
+                interaction.user.id
+            ))
+            .components(vec![]),
+    );
+    if let Err(e) = interaction.create_response(ctx, response).await {
+        log::error!("Failed to update review {}: {}", decision_id, e);
+    }
+}
@@ -0,0 +1,1026 @@
+use std::{path::Path, sync::Mutex};
+
+use rand::{distributions::Alphanumeric, Rng};
+use rusqlite::{params, Connection, OptionalExtension};
+use serenity::all::{ChannelId, GuildId, MessageId, UserId};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    config::{ContentStorage, EscalationStep},
+    escalation::{decay, unix_now},
+    policy::Action,
+    verdict::ModerationVerdict,
+};
+
+static MIGRATIONS: [&str; 9] = [
+    "CREATE TABLE meta (
+        key TEXT PRIMARY KEY,
+        value TEXT NOT NULL
+    );
+    CREATE TABLE decisions (
+        id INTEGER PRIMARY KEY,
+        created_at INTEGER NOT NULL,
+        guild_id INTEGER,
+        channel_id INTEGER NOT NULL,
+        author_id INTEGER NOT NULL,
+        message_id INTEGER NOT NULL,
+        content TEXT,
+        content_hash TEXT,
+        edited INTEGER NOT NULL,
+        score INTEGER NOT NULL,
+        categories TEXT NOT NULL,
+        reason TEXT NOT NULL,
+        trigger TEXT,
+        model TEXT NOT NULL,
+        latency_ms INTEGER NOT NULL,
+        action TEXT NOT NULL
+    );
+    CREATE INDEX decisions_author ON decisions (guild_id, author_id, created_at);
+    CREATE INDEX decisions_created_at ON decisions (created_at);",
+    "CREATE TABLE strikes (
+        guild_id INTEGER NOT NULL,
+        user_id INTEGER NOT NULL,
+        strikes REAL NOT NULL,
+        updated_at INTEGER NOT NULL,
+        PRIMARY KEY (guild_id, user_id)
+    );
+    CREATE TABLE escalations (
+        id INTEGER PRIMARY KEY,
+        created_at INTEGER NOT NULL,
+        guild_id INTEGER NOT NULL,
+        user_id INTEGER NOT NULL,
+        message_id INTEGER NOT NULL,
+        action TEXT NOT NULL,
+        duration_minutes INTEGER NOT NULL,
+        strikes_before REAL NOT NULL,
+        strikes_after REAL NOT NULL,
+        error TEXT
+    );
+    CREATE INDEX escalations_user ON escalations (guild_id, user_id, created_at);",
+    "CREATE TABLE reviews (
+        decision_id INTEGER PRIMARY KEY,
+        guild_id INTEGER NOT NULL,
+        channel_id INTEGER NOT NULL,
+        author_id INTEGER NOT NULL,
+        author_name TEXT NOT NULL,
+        author_avatar TEXT NOT NULL,
+        message_id INTEGER NOT NULL,
+        content TEXT,
+        action TEXT NOT NULL,
+        strikes REAL NOT NULL,
+        outcome TEXT,
+        reviewer_id INTEGER,
+        reviewed_at INTEGER
+    );",
+    "CREATE TABLE appeals (
+        decision_id INTEGER PRIMARY KEY,
+        guild_id INTEGER NOT NULL,
+        user_id INTEGER NOT NULL,
+        content TEXT,
+        strikes REAL NOT NULL,
+        statement TEXT,
+        submitted_at INTEGER,
+        outcome TEXT,
+        moderator_id INTEGER,
+        decided_at INTEGER
+    );",
+    "ALTER TABLE decisions ADD COLUMN shadow INTEGER NOT NULL DEFAULT 0;",
+    "ALTER TABLE decisions ADD COLUMN provider_results TEXT NOT NULL DEFAULT '[]';",
+    "ALTER TABLE decisions ADD COLUMN prefilter_rule TEXT;
+    CREATE INDEX decisions_content_hash ON decisions (guild_id, content_hash);",
+    "CREATE INDEX decisions_message ON decisions (message_id);",
+    "ALTER TABLE escalations ADD COLUMN reversed INTEGER NOT NULL DEFAULT 0;",
+];
+
+pub struct DecisionRecord<'a> {
+    pub guild_id: Option<GuildId>,
+    pub channel_id: ChannelId,
+    pub author_id: UserId,
+    pub message_id: MessageId,
+    pub content: &'a str,
+    pub storage: ContentStorage,
+    pub edited: bool,
+    pub verdict: &'a ModerationVerdict,
+    pub trigger: Option<&'a str>,
+    pub action: Action,
+    pub shadow: bool,
+}
+
+pub struct EscalationRecord<'a> {
+    pub guild_id: GuildId,
+    pub user_id: UserId,
+    pub message_id: MessageId,
+    pub step: &'a EscalationStep,
+    pub before: f32,
+    pub after: f32,
+    pub error: Option<String>,
+}
+
+pub struct Review {
+    pub decision_id: i64,
+    pub guild_id: GuildId,
+    pub channel_id: ChannelId,
+    pub author_id: UserId,
+    pub author_name: String,
+    pub author_avatar: String,
+    pub message_id: MessageId,
+    pub content: Option<String>,
+    pub action: String,
+    pub strikes: f32,
+}
+
+pub struct Appeal {
+    pub decision_id: i64,
+    pub guild_id: GuildId,
+    pub channel_id: ChannelId,
+    pub user_id: UserId,
+    pub content: Option<String>,
+    pub strikes: f32,
+    pub statement: String,
+    pub score: u16,
+    pub reason: String,
+    pub action: String,
+}
+
+pub struct HistoryEntry {
+    pub created_at: i64,
+    pub channel_id: ChannelId,
+    pub edited: bool,
+    pub score: u16,
+    pub reason: String,
+    pub action: String,
+}
+
+pub struct EscalationEntry {
+    pub created_at: i64,
+    pub action: String,
+    pub duration_minutes: u64,
+    pub failed: bool,
+    pub reversed: bool,
+}
+
+// Calls lock the connection from async tasks. Every query but `prune` is a
+// single indexed lookup or write on a local WAL database, quicker than
+// handing it to a blocking thread; `prune` runs under `spawn_blocking`.
+pub struct Store {
+    conn: Mutex<Connection>,
+    salt: String,
+}
+
+fn migrate(conn: &mut Connection) -> rusqlite::Result<()> {
+    let version: usize = conn.pragma_query_value(None, "user_version", |r| r.get(0))?;
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", i + 1)?;
+        tx.commit()?;
+        log::info!("Applied store migration {}", i + 1);
+    }
+    Ok(())
+}
+
+impl Store {
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        let mut conn = Connection::open(path)?;
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |r| r.get::<_, String>(0))?;
+        migrate(&mut conn)?;
+
+        let salt = match conn
+            .query_row("SELECT value FROM meta WHERE key = 'salt'", [], |r| {
+                r.get(0)
+            })
+            .optional()?
+        {
+            Some(salt) => salt,
+            None => {
+                let salt = rand::thread_rng()
+                    .sample_iter(&Alphanumeric)
+                    .take(32)
+                    .map(char::from)
+                    .collect::<String>();
+                conn.execute(
+                    "INSERT INTO meta (key, value) VALUES ('salt', ?1)",
+                    params![salt],
+                )?;
+                salt
+            }
+        };
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+            salt,
+        })
+    }
+
+    pub fn hash(&self, content: &str) -> String {
+        Sha256::digest(format!("{}{}", self.salt, content))
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    pub fn record_decision(&self, record: &DecisionRecord) -> rusqlite::Result<i64> {
+        let (content, content_hash) = match record.storage {
+            ContentStorage::None => (None, None),
+            ContentStorage::Hash => (None, Some(self.hash(record.content))),
+            ContentStorage::Full => (
+                Some(record.content.to_string()),
+                Some(self.hash(record.content)),
+            ),
+        };
+        let verdict = record.verdict;
+
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO decisions (created_at, guild_id, channel_id, author_id, message_id,
+                content, content_hash, edited, score, categories, reason, trigger, model,
+                latency_ms, action, shadow, provider_results, prefilter_rule)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17,
+                ?18)",
+            params![
+                unix_now() as i64,
+                record.guild_id.map(|id| id.get() as i64),
+                record.channel_id.get() as i64,
+                record.author_id.get() as i64,
+                record.message_id.get() as i64,
+                content,
+                content_hash,
+                record.edited,
+                verdict.score,
+                serde_json::to_string(&verdict.categories).unwrap_or_default(),
+                verdict.reason,
+                record.trigger,
+                verdict.model,
+                verdict.latency_ms as i64,
+                record.action.to_string(),
+                record.shadow,
+                serde_json::to_string(&verdict.provider_results).unwrap_or_default(),
+                verdict.prefilter_rule,
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    // Text is known to be safe once a model has passed it the given number of
+    // times in this guild and never acted on it. Only guilds that store at
+    // least a hash of the content build up this history.
+    pub fn known_safe(
+        &self,
+        guild_id: GuildId,
+        content: &str,
+        repeats: u32,
+    ) -> rusqlite::Result<bool> {
+        let (clean, flagged): (u32, u32) = self.conn.lock().unwrap().query_row(
+            "SELECT COUNT(*) FILTER (WHERE action = 'none' AND prefilter_rule IS NULL),
+                COUNT(*) FILTER (WHERE action != 'none')
+            FROM decisions WHERE guild_id = ?1 AND content_hash = ?2",
+            params![guild_id.get() as i64, self.hash(content)],
+            |r| Ok((r.get(0)?, r.get(1)?)),
+        )?;
+        Ok(clean >= repeats && flagged == 0)
+    }
+
+    // Whether the last decision on this message was made on the same text.
+    // Without a stored hash (store_content "none") this is never the case.
+    pub fn last_content_matches(
+        &self,
+        message_id: MessageId,
+        content: &str,
+    ) -> rusqlite::Result<bool> {
+        let hash: Option<Option<String>> = self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT content_hash FROM decisions WHERE message_id = ?1
+                ORDER BY id DESC LIMIT 1",
+                params![message_id.get() as i64],
+                |r| r.get(0),
+            )
+            .optional()?;
+        Ok(hash.flatten() == Some(self.hash(content)))
+    }
+
+    // The text the last decision on this message was made on, when the guild
+    // keeps full content.
+    pub fn last_content(&self, message_id: MessageId) -> rusqlite::Result<Option<String>> {
+        let content: Option<Option<String>> = self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT content FROM decisions WHERE message_id = ?1
+                ORDER BY id DESC LIMIT 1",
+                params![message_id.get() as i64],
+                |r| r.get(0),
+            )
+            .optional()?;
+        Ok(content.flatten())
+    }
+
+    // Content is only given here when the guild keeps full content, and is
+    // dropped again once the review is resolved.
+    pub fn open_review(&self, review: &Review) -> rusqlite::Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO reviews (decision_id, guild_id, channel_id, author_id, author_name,
+                author_avatar, message_id, content, action, strikes)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                review.decision_id,
+                review.guild_id.get() as i64,
+                review.channel_id.get() as i64,
+                review.author_id.get() as i64,
+                review.author_name,
+                review.author_avatar,
+                review.message_id.get() as i64,
+                review.content,
+                review.action,
+                review.strikes as f64,
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn resolve_review(
+        &self,
+        decision_id: i64,
+        outcome: &str,
+        reviewer_id: UserId,
+    ) -> rusqlite::Result<Option<Review>> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let review = tx
+            .query_row(
+                "SELECT r.decision_id, r.guild_id, r.channel_id, r.author_id, r.author_name,
+                    r.author_avatar, r.message_id, r.content, r.action,
+                    CASE WHEN a.outcome = 'accepted' THEN 0 ELSE r.strikes END
+                FROM reviews r LEFT JOIN appeals a ON a.decision_id = r.decision_id
+                WHERE r.decision_id = ?1 AND r.outcome IS NULL",
+                params![decision_id],
+                |r| {
+                    Ok(Review {
+                        decision_id: r.get(0)?,
+                        guild_id: GuildId::new(r.get::<_, i64>(1)? as u64),
+                        channel_id: ChannelId::new(r.get::<_, i64>(2)? as u64),
+                        author_id: UserId::new(r.get::<_, i64>(3)? as u64),
+                        author_name: r.get(4)?,
+                        author_avatar: r.get(5)?,
+                        message_id: MessageId::new(r.get::<_, i64>(6)? as u64),
+                        content: r.get(7)?,
+                        action: r.get(8)?,
+                        strikes: r.get::<_, f64>(9)? as f32,
+                    })
+                },
+            )
+            .optional()?;
+        if review.is_some() {
+            tx.execute(
+                "UPDATE reviews SET outcome = ?2, reviewer_id = ?3, reviewed_at = ?4, content = NULL
+                WHERE decision_id = ?1",
+                params![
+                    decision_id,
+                    outcome,
+                    reviewer_id.get() as i64,
+                    unix_now() as i64
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(review)
+    }
+
+    pub fn reopen_review(&self, review: &Review) -> rusqlite::Result<()> {
+        self.conn.lock().unwrap().execute(
+            "UPDATE reviews SET outcome = NULL, reviewer_id = NULL, reviewed_at = NULL,
+                content = ?2
+            WHERE decision_id = ?1",
+            params![review.decision_id, review.content],
+        )?;
+        Ok(())
+    }
+
+    // Like reviews, any content is only kept until the appeal is decided.
+    pub fn offer_appeal(
+        &self,
+        decision_id: i64,
+        guild_id: GuildId,
+        user_id: UserId,
+        content: Option<&str>,
+        strikes: f32,
+    ) -> rusqlite::Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO appeals (decision_id, guild_id, user_id, content, strikes)
+            VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                decision_id,
+                guild_id.get() as i64,
+                user_id.get() as i64,
+                content,
+                strikes as f64,
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn appeal(conn: &Connection, decision_id: i64) -> rusqlite::Result<Option<Appeal>> {
+        conn.query_row(
+            "SELECT a.decision_id, a.guild_id, d.channel_id, a.user_id, a.content,
+                CASE WHEN r.outcome IN ('restored', 'dismissed') THEN 0 ELSE a.strikes END,
+                a.statement, d.score, d.reason, d.action
+            FROM appeals a
+            JOIN decisions d ON d.id = a.decision_id
+            LEFT JOIN reviews r ON r.decision_id = a.decision_id
+            WHERE a.decision_id = ?1",
+            params![decision_id],
+            |r| {
+                Ok(Appeal {
+                    decision_id: r.get(0)?,
+                    guild_id: GuildId::new(r.get::<_, i64>(1)? as u64),
+                    channel_id: ChannelId::new(r.get::<_, i64>(2)? as u64),
+                    user_id: UserId::new(r.get::<_, i64>(3)? as u64),
+                    content: r.get(4)?,
+                    strikes: r.get::<_, f64>(5)? as f32,
+                    statement: r.get::<_, Option<String>>(6)?.unwrap_or_default(),
+                    score: r.get(7)?,
+                    reason: r.get(8)?,
+                    action: r.get(9)?,
+                })
+            },
+        )
+        .optional()
+    }
+
+    pub fn submit_appeal(
+        &self,
+        decision_id: i64,
+        user_id: UserId,
+        statement: &str,
+    ) -> rusqlite::Result<Option<Appeal>> {
+        let conn = self.conn.lock().unwrap();
+        let updated = conn.execute(
+            "UPDATE appeals SET statement = ?3, submitted_at = ?4
+            WHERE decision_id = ?1 AND user_id = ?2 AND statement IS NULL",
+            params![
+                decision_id,
+                user_id.get() as i64,
+                statement,
+                unix_now() as i64
+            ],
+        )?;
+        if updated == 0 {
+            return Ok(None);
+        }
+        Self::appeal(&conn, decision_id)
+    }
+
+    pub fn decide_appeal(
+        &self,
+        decision_id: i64,
+        outcome: &str,
+        moderator_id: UserId,
+    ) -> rusqlite::Result<Option<Appeal>> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let updated = tx.execute(
+            "UPDATE appeals SET outcome = ?2, moderator_id = ?3, decided_at = ?4
+            WHERE decision_id = ?1 AND statement IS NOT NULL AND outcome IS NULL",
+            params![
+                decision_id,
+                outcome,
+                moderator_id.get() as i64,
+                unix_now() as i64
+            ],
+        )?;
+        if updated == 0 {
+            return Ok(None);
+        }
+        let appeal = Self::appeal(&tx, decision_id)?;
+        tx.execute(
+            "UPDATE appeals SET content = NULL WHERE decision_id = ?1",
+            params![decision_id],
+        )?;
+        tx.commit()?;
+        Ok(appeal)
+    }
+
+    pub fn add_strikes(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        weight: f32,
+        half_life_hours: f32,
+    ) -> rusqlite::Result<(f32, f32)> {
+        let now = unix_now();
+        let conn = self.conn.lock().unwrap();
+
+        let before = conn
+            .query_row(
+                "SELECT strikes, updated_at FROM strikes WHERE guild_id = ?1 AND user_id = ?2",
+                params![guild_id.get() as i64, user_id.get() as i64],
+                |r| Ok((r.get::<_, f64>(0)?, r.get::<_, i64>(1)?)),
+            )
+            .optional()?
+            .map(|(strikes, updated_at)| {
+                decay(
+                    strikes as f32,
+                    now.saturating_sub(updated_at as u64),
+                    half_life_hours,
+                )
+            })
+            .unwrap_or(0.0);
+        let after = (before + weight).max(0.0);
+
+        conn.execute(
+            "INSERT INTO strikes (guild_id, user_id, strikes, updated_at) VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT (guild_id, user_id)
+            DO UPDATE SET strikes = excluded.strikes, updated_at = excluded.updated_at",
+            params![
+                guild_id.get() as i64,
+                user_id.get() as i64,
+                after as f64,
+                now as i64
+            ],
+        )?;
+        Ok((before, after))
+    }
+
+    pub fn record_escalation(&self, record: &EscalationRecord) -> rusqlite::Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO escalations (created_at, guild_id, user_id, message_id, action,
+                duration_minutes, strikes_before, strikes_after, error)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                unix_now() as i64,
+                record.guild_id.get() as i64,
+                record.user_id.get() as i64,
+                record.message_id.get() as i64,
+                record.step.action.to_string(),
+                record.step.duration_minutes as i64,
+                record.before as f64,
+                record.after as f64,
+                record.error,
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn strikes(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        half_life_hours: f32,
+    ) -> rusqlite::Result<f32> {
+        let strikes = self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT strikes, updated_at FROM strikes WHERE guild_id = ?1 AND user_id = ?2",
+                params![guild_id.get() as i64, user_id.get() as i64],
+                |r| Ok((r.get::<_, f64>(0)?, r.get::<_, i64>(1)?)),
+            )
+            .optional()?
+            .map(|(strikes, updated_at)| {
+                decay(
+                    strikes as f32,
+                    unix_now().saturating_sub(updated_at as u64),
+                    half_life_hours,
+                )
+            })
+            .unwrap_or(0.0);
+        Ok(strikes)
+    }
+
+    pub fn history(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        limit: usize,
+    ) -> rusqlite::Result<Vec<HistoryEntry>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT created_at, channel_id, edited, score, reason, action
+            FROM decisions
+            WHERE guild_id = ?1 AND author_id = ?2 AND action != 'none' AND NOT shadow
+            ORDER BY created_at DESC, id DESC LIMIT ?3",
+        )?;
+        let rows = stmt.query_map(
+            params![guild_id.get() as i64, user_id.get() as i64, limit as i64],
+            |r| {
+                Ok(HistoryEntry {
+                    created_at: r.get(0)?,
+                    channel_id: ChannelId::new(r.get::<_, i64>(1)? as u64),
+                    edited: r.get(2)?,
+                    score: r.get(3)?,
+                    reason: r.get(4)?,
+                    action: r.get(5)?,
+                })
+            },
+        )?;
+        rows.collect()
+    }
+
+    pub fn escalation_history(
+        &self,
+        guild_id: GuildId,
+        user_id: UserId,
+        limit: usize,
+    ) -> rusqlite::Result<Vec<EscalationEntry>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT created_at, action, duration_minutes, error IS NOT NULL, reversed
+            FROM escalations WHERE guild_id = ?1 AND user_id = ?2
+            ORDER BY created_at DESC, id DESC LIMIT ?3",
+        )?;
+        let rows = stmt.query_map(
+            params![guild_id.get() as i64, user_id.get() as i64, limit as i64],
+            |r| {
+                Ok(EscalationEntry {
+                    created_at: r.get(0)?,
+                    action: r.get(1)?,
+                    duration_minutes: r.get::<_, i64>(2)? as u64,
+                    failed: r.get(3)?,
+                    reversed: r.get(4)?,
+                })
+            },
+        )?;
+        rows.collect()
+    }
+
+    // Marks the escalations the given decision led to as reversed, returning
+    // the ones that had been carried out.
+    pub fn reverse_escalations(&self, decision_id: i64) -> rusqlite::Result<Vec<EscalationEntry>> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let entries = {
+            let mut stmt = tx.prepare(
+                "SELECT e.created_at, e.action, e.duration_minutes, e.error IS NOT NULL
+                FROM escalations e JOIN decisions d ON d.message_id = e.message_id
+                    AND d.guild_id = e.guild_id AND d.author_id = e.user_id
+                WHERE d.id = ?1 AND NOT e.reversed
+                ORDER BY e.id",
+            )?;
+            let rows = stmt.query_map(params![decision_id], |r| {
+                Ok(EscalationEntry {
+                    created_at: r.get(0)?,
+                    action: r.get(1)?,
+                    duration_minutes: r.get::<_, i64>(2)? as u64,
+                    failed: r.get(3)?,
+                    reversed: true,
+                })
+            })?;
+            rows.collect::<rusqlite::Result<Vec<_>>>()?
+        };
+        tx.execute(
+            "UPDATE escalations SET reversed = 1 WHERE NOT reversed AND id IN
+                (SELECT e.id FROM escalations e JOIN decisions d ON d.message_id = e.message_id
+                    AND d.guild_id = e.guild_id AND d.author_id = e.user_id
+                WHERE d.id = ?1)",
+            params![decision_id],
+        )?;
+        tx.commit()?;
+        Ok(entries.into_iter().filter(|e| !e.failed).collect())
+    }
+
+    pub fn action_counts(
+        &self,
+        guild_id: GuildId,
+        since_secs: u64,
+    ) -> rusqlite::Result<Vec<(String, u64)>> {
+        let since = unix_now().saturating_sub(since_secs) as i64;
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT CASE WHEN shadow THEN 'would ' || action ELSE action END AS label, COUNT(*)
+            FROM decisions WHERE guild_id = ?1 AND created_at >= ?2
+            GROUP BY label ORDER BY label",
+        )?;
+        let rows = stmt.query_map(params![guild_id.get() as i64, since], |r| {
+            Ok((r.get(0)?, r.get::<_, i64>(1)? as u64))
+        })?;
+        rows.collect()
+    }
+
+    pub fn prune(&self, retention_days: u64) -> rusqlite::Result<usize> {
+        if retention_days == 0 {
+            return Ok(0);
+        }
+
+        let cutoff = unix_now().saturating_sub(retention_days * 60 * 60 * 24) as i64;
+        let conn = self.conn.lock().unwrap();
+        let mut pruned = 0;
+        for table in ["reviews", "appeals"] {
+            pruned += conn.execute(
+                &format!(
+                    "DELETE FROM {} WHERE decision_id IN
+                        (SELECT id FROM decisions WHERE created_at < ?1)",
+                    table
+                ),
+                params![cutoff],
+            )?;
+        }
+        for table in ["decisions", "escalations"] {
+            pruned += conn.execute(
+                &format!("DELETE FROM {} WHERE created_at < ?1", table),
+                params![cutoff],
+            )?;
+        }
+        pruned += conn.execute("DELETE FROM strikes WHERE updated_at < ?1", params![cutoff])?;
+        Ok(pruned)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Punishment;
+
+    fn store() -> Store {
+        Store::open(Path::new(":memory:")).unwrap()
+    }
+
+    fn verdict() -> ModerationVerdict {
+        ModerationVerdict::parse(r#"{"score": 800, "reason": "insult", "confidence": 0.9}"#)
+            .unwrap()
+    }
+
+    fn decide(store: &Store, content: &str, storage: ContentStorage) -> i64 {
+        store
+            .record_decision(&DecisionRecord {
+                guild_id: Some(GuildId::new(1)),
+                channel_id: ChannelId::new(2),
+                author_id: UserId::new(4),
+                message_id: MessageId::new(3),
+                content,
+                storage,
+                edited: false,
+                verdict: &verdict(),
+                trigger: None,
+                action: Action::Delete,
+                shadow: false,
+            })
+            .unwrap()
+    }
+
+    fn review(decision_id: i64) -> Review {
+        Review {
+            decision_id,
+            guild_id: GuildId::new(1),
+            channel_id: ChannelId::new(2),
+            author_id: UserId::new(4),
+            author_name: "user".to_string(),
+            author_avatar: String::new(),
+            message_id: MessageId::new(3),
+            content: Some("you idiot".to_string()),
+            action: "delete".to_string(),
+            strikes: 2.0,
+        }
+    }
+
+    fn age(store: &Store, days: u64) {
+        let then = unix_now().saturating_sub(days * 60 * 60 * 24) as i64;
+        let conn = store.conn.lock().unwrap();
+        conn.execute("UPDATE decisions SET created_at = ?1", params![then])
+            .unwrap();
+        conn.execute("UPDATE strikes SET updated_at = ?1", params![then])
+            .unwrap();
+    }
+
+    fn count(store: &Store, table: &str) -> i64 {
+        store
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(&format!("SELECT COUNT(*) FROM {}", table), [], |r| r.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn migrations_upgrade_older_stores() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        for migration in &MIGRATIONS[..4] {
+            conn.execute_batch(migration).unwrap();
+        }
+        conn.pragma_update(None, "user_version", 4).unwrap();
+
+        migrate(&mut conn).unwrap();
+        migrate(&mut conn).unwrap();
+        let version: usize = conn
+            .pragma_query_value(None, "user_version", |r| r.get(0))
+            .unwrap();
+        assert_eq!(version, MIGRATIONS.len());
+        conn.execute(
+            "SELECT shadow, provider_results, prefilter_rule FROM decisions",
+            [],
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn content_is_kept_as_configured() {
+        let store = store();
+        let none = decide(&store, "you idiot", ContentStorage::None);
+        let hash = decide(&store, "you idiot", ContentStorage::Hash);
+        let full = decide(&store, "you idiot", ContentStorage::Full);
+
+        let stored = |id: i64| {
+            store
+                .conn
+                .lock()
+                .unwrap()
+                .query_row(
+                    "SELECT content, content_hash FROM decisions WHERE id = ?1",
+                    params![id],
+                    |r| {
+                        Ok((
+                            r.get::<_, Option<String>>(0)?,
+                            r.get::<_, Option<String>>(1)?,
+                        ))
+                    },
+                )
+                .unwrap()
+        };
+        assert_eq!(stored(none), (None, None));
+        assert_eq!(stored(hash), (None, Some(store.hash("you idiot"))));
+        assert_eq!(
+            stored(full),
+            (Some("you idiot".to_string()), Some(store.hash("you idiot")))
+        );
+
+        assert_eq!(
+            store.last_content(MessageId::new(3)).unwrap().as_deref(),
+            Some("you idiot")
+        );
+        decide(&store, "you idiot", ContentStorage::None);
+        assert!(!store
+            .last_content_matches(MessageId::new(3), "you idiot")
+            .unwrap());
+        assert_eq!(store.last_content(MessageId::new(3)).unwrap(), None);
+    }
+
+    #[test]
+    fn prune_drops_old_records() {
+        let store = store();
+        let id = decide(&store, "you idiot", ContentStorage::Full);
+        store.open_review(&review(id)).unwrap();
+        store
+            .offer_appeal(id, GuildId::new(1), UserId::new(4), None, 2.0)
+            .unwrap();
+        store
+            .add_strikes(GuildId::new(1), UserId::new(4), 2.0, 24.0)
+            .unwrap();
+
+        assert_eq!(store.prune(0).unwrap(), 0);
+        assert_eq!(store.prune(30).unwrap(), 0);
+
+        age(&store, 31);
+        assert_eq!(store.prune(30).unwrap(), 4);
+        for table in ["decisions", "reviews", "appeals", "strikes"] {
+            assert_eq!(count(&store, table), 0, "{}", table);
+        }
+    }
+
+    #[test]
+    fn strikes_decay() {
+        let store = store();
+        let (guild, user) = (GuildId::new(1), UserId::new(4));
+        assert_eq!(store.strikes(guild, user, 24.0).unwrap(), 0.0);
+        assert_eq!(
+            store.add_strikes(guild, user, 2.0, 24.0).unwrap(),
+            (0.0, 2.0)
+        );
+
+        store
+            .conn
+            .lock()
+            .unwrap()
+            .execute(
+                "UPDATE strikes SET updated_at = ?1",
+                params![unix_now() as i64 - 24 * 60 * 60],
+            )
+            .unwrap();
+        assert!((store.strikes(guild, user, 24.0).unwrap() - 1.0).abs() < 0.01);
+
+        let (before, after) = store.add_strikes(guild, user, -2.0, 24.0).unwrap();
+        assert!((before - 1.0).abs() < 0.01);
+        assert_eq!(after, 0.0);
+    }
+
+    #[test]
+    fn reviews_resolve_once() {
+        let store = store();
+        let id = decide(&store, "you idiot", ContentStorage::Full);
+        store.open_review(&review(id)).unwrap();
+
+        let resolved = store
+            .resolve_review(id, "restored", UserId::new(9))
+            .unwrap()
+            .unwrap();
+        assert_eq!(resolved.content.as_deref(), Some("you idiot"));
+        assert!(store
+            .resolve_review(id, "confirmed", UserId::new(9))
+            .unwrap()
+            .is_none());
+
+        // A failed restore reopens the review with its content.
+        store.reopen_review(&resolved).unwrap();
+        let resolved = store
+            .resolve_review(id, "confirmed", UserId::new(9))
+            .unwrap()
+            .unwrap();
+        assert_eq!(resolved.content.as_deref(), Some("you idiot"));
+        assert_eq!(count(&store, "reviews WHERE content IS NOT NULL"), 0);
+    }
+
+    #[test]
+    fn appeals_are_decided_once() {
+        let store = store();
+        let id = decide(&store, "you idiot", ContentStorage::Full);
+        store
+            .offer_appeal(id, GuildId::new(1), UserId::new(4), Some("you idiot"), 2.0)
+            .unwrap();
+
+        // Only the warned user can appeal, and only once.
+        assert!(store
+            .submit_appeal(id, UserId::new(5), "not me")
+            .unwrap()
+            .is_none());
+        assert!(store
+            .decide_appeal(id, "accepted", UserId::new(9))
+            .unwrap()
+            .is_none());
+        let appeal = store
+            .submit_appeal(id, UserId::new(4), "it was a joke")
+            .unwrap()
+            .unwrap();
+        assert_eq!(appeal.statement, "it was a joke");
+        assert!(store
+            .submit_appeal(id, UserId::new(4), "again")
+            .unwrap()
+            .is_none());
+
+        let decided = store
+            .decide_appeal(id, "accepted", UserId::new(9))
+            .unwrap()
+            .unwrap();
+        assert_eq!(decided.content.as_deref(), Some("you idiot"));
+        assert_eq!(decided.strikes, 2.0);
+        assert!(store
+            .decide_appeal(id, "rejected", UserId::new(9))
+            .unwrap()
+            .is_none());
+        assert_eq!(count(&store, "appeals WHERE content IS NOT NULL"), 0);
+    }
+
+    #[test]
+    fn accepted_appeals_leave_no_strikes_to_restore() {
+        let store = store();
+        let id = decide(&store, "you idiot", ContentStorage::Full);
+        store.open_review(&review(id)).unwrap();
+        store
+            .offer_appeal(id, GuildId::new(1), UserId::new(4), None, 2.0)
+            .unwrap();
+        store
+            .submit_appeal(id, UserId::new(4), "it was a joke")
+            .unwrap();
+        store.decide_appeal(id, "accepted", UserId::new(9)).unwrap();
+
+        let review = store
+            .resolve_review(id, "dismissed", UserId::new(9))
+            .unwrap()
+            .unwrap();
+        assert_eq!(review.strikes, 0.0);
+    }
+
+    #[test]
+    fn escalations_are_reversed_once() {
+        let store = store();
+        let id = decide(&store, "you idiot", ContentStorage::Full);
+        for (action, error) in [(Punishment::Timeout, None), (Punishment::Kick, Some("no"))] {
+            store
+                .record_escalation(&EscalationRecord {
+                    guild_id: GuildId::new(1),
+                    user_id: UserId::new(4),
+                    message_id: MessageId::new(3),
+                    step: &EscalationStep {
+                        strikes: 3.0,
+                        action,
+                        duration_minutes: 10,
+                    },
+                    before: 1.0,
+                    after: 3.0,
+                    error: error.map(str::to_string),
+                })
+                .unwrap();
+        }
+
+        let reversed = store.reverse_escalations(id).unwrap();
+        assert_eq!(reversed.len(), 1);
+        assert_eq!(reversed[0].action, "timeout");
+        assert!(store.reverse_escalations(id).unwrap().is_empty());
+        assert!(store
+            .escalation_history(GuildId::new(1), UserId::new(4), 5)
+            .unwrap()
+            .iter()
+            .all(|e| e.reversed));
+    }
+}
@@ -0,0 +1,245 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{constants::MAX_SCORE, defs::GeminiSchema, enums::GeminiSchemaType};
+
+pub static CATEGORIES: [&str; 6] = [
+    "harassment",
+    "hate_speech",
+    "spam",
+    "sexual",
+    "violence",
+    "other",
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CategoryScore {
+    pub category: String,
+    pub score: u16,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderResult {
+    pub provider: String,
+    pub model: String,
+    pub score: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub latency_ms: u64,
+}
+
+// Keys a model adds of its own are ignored rather than failing the verdict,
+// since a verdict that fails to parse leaves the message unmoderated.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModerationVerdict {
+    pub score: u16,
+    #[serde(default)]
+    pub categories: Vec<CategoryScore>,
+    #[serde(default)]
+    pub reason: String,
+    pub rule_violated: Option<String>,
+    pub confidence: f32,
+    #[serde(default)]
+    pub message_id: String,
+    #[serde(skip)]
+    pub model: String,
+    #[serde(skip)]
+    pub latency_ms: u64,
+    #[serde(skip)]
+    pub provider_results: Vec<ProviderResult>,
+    #[serde(skip)]
+    pub prefilter_rule: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum VerdictError {
+    Empty,
+    Malformed(serde_json::Error, String),
+    OutOfRange(String),
+}
+
+impl fmt::Display for VerdictError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Empty => write!(f, "model returned no verdict"),
+            Self::Malformed(e, raw) => write!(f, "malformed verdict ({}): {:?}", e, raw),
+            Self::OutOfRange(reason) => write!(f, "verdict out of range: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for VerdictError {}
+
+impl ModerationVerdict {
+    pub fn schema() -> GeminiSchema {
+        GeminiSchema::new(GeminiSchemaType::Object)
+            .property(
+                "score",
+                GeminiSchema::new(GeminiSchemaType::Integer)
+                    .description("0 (clean) to 1000 (clear violation)"),
+                true,
+            )
+            .property(
+                "categories",
+                GeminiSchema::new(GeminiSchemaType::Array).items(
+                    GeminiSchema::new(GeminiSchemaType::Object)
+                        .property(
+                            "category",
+                            GeminiSchema::new(GeminiSchemaType::String)
+                                .enum_values(CATEGORIES.iter().map(|c| c.to_string()).collect()),
+                            true,
+                        )
+                        .property(
+                            "score",
+                            GeminiSchema::new(GeminiSchemaType::Integer)
+                                .description("0 (clean) to 1000 (clear violation)"),
+                            true,
+                        ),
+                ),
+                true,
+            )
+            .property("reason", GeminiSchema::new(GeminiSchemaType::String), true)
+            .property(
+                "rule_violated",
+                GeminiSchema::new(GeminiSchemaType::String)
+                    .description("the rule that was broken, omitted if none"),
+                false,
+            )
+            .property(
+                "confidence",
+                GeminiSchema::new(GeminiSchemaType::Number).description("0.0 to 1.0"),
+                true,
+            )
+            .property(
+                "message_id",
+                GeminiSchema::new(GeminiSchemaType::String)
+                    .description("the id attribute of the <message> tag that was scored"),
+                true,
+            )
+    }
+
+    // The same schema in JSON Schema form, for OpenAI-compatible servers.
+    pub fn json_schema() -> serde_json::Value {
+        let score = serde_json::json!({
+            "type": "integer",
+            "minimum": 0,
+            "maximum": MAX_SCORE,
+            "description": "0 (clean) to 1000 (clear violation)"
+        });
+        serde_json::json!({
+            "type": "object",
+            "properties": {
+                "score": score,
+                "categories": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "category": { "type": "string", "enum": CATEGORIES },
+                            "score": score
+                        },
+                        "required": ["category", "score"],
+                        "additionalProperties": false
+                    }
+                },
+                "reason": { "type": "string" },
+                "rule_violated": {
+                    "type": "string",
+                    "description": "the rule that was broken, omitted if none"
+                },
+                "confidence": { "type": "number", "minimum": 0.0, "maximum": 1.0 },
+                "message_id": {
+                    "type": "string",
+                    "description": "the id attribute of the <message> tag that was scored"
+                }
+            },
+            "required": ["score", "categories", "reason", "confidence", "message_id"],
+            "additionalProperties": false
+        })
+    }
+
+    pub fn parse(text: &str) -> Result<Self, VerdictError> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(VerdictError::Empty);
+        }
+
+        let mut verdict: Self =
+            serde_json::from_str(text).map_err(|e| VerdictError::Malformed(e, text.to_string()))?;
+
+        if verdict.score > MAX_SCORE {
+            return Err(VerdictError::OutOfRange(format!(
+                "score {} is above {}",
+                verdict.score, MAX_SCORE
+            )));
+        }
+        if !(0.0..=1.0).contains(&verdict.confidence) {
+            return Err(VerdictError::OutOfRange(format!(
+                "confidence {} is outside 0.0..=1.0",
+                verdict.confidence
+            )));
+        }
+        for c in &verdict.categories {
+            if c.score > MAX_SCORE {
+                return Err(VerdictError::OutOfRange(format!(
+                    "{} score {} is above {}",
+                    c.category, c.score, MAX_SCORE
+                )));
+            }
+        }
+
+        // Categories a model makes up still count, as "other".
+        let mut categories: Vec<CategoryScore> = vec![];
+        for c in verdict.categories.drain(..) {
+            let category = if CATEGORIES.contains(&c.category.as_str()) {
+                c.category
+            } else {
+                "other".to_string()
+            };
+            match categories.iter_mut().find(|e| e.category == category) {
+                Some(e) => e.score = e.score.max(c.score),
+                None => categories.push(CategoryScore {
+                    category,
+                    score: c.score,
+                }),
+            }
+        }
+        verdict.categories = categories;
+
+        Ok(verdict)
+    }
+
+    pub fn provider_summary(&self) -> String {
+        let summary = self
+            .provider_results
+            .iter()
+            .map(|r| match (&r.score, &r.error) {
+                (Some(score), _) => format!("{} {}", r.provider, score),
+                (None, Some(error)) => format!("{} failed ({})", r.provider, error),
+                (None, None) => format!("{} failed", r.provider),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        if summary.is_empty() {
+            "-".to_string()
+        } else {
+            summary
+        }
+    }
+
+    pub fn breakdown(&self) -> String {
+        let mut categories = self.categories.iter().collect::<Vec<_>>();
+        categories.sort_by(|a, b| b.score.cmp(&a.score));
+        let breakdown = categories
+            .iter()
+            .map(|c| format!("{} {}", c.category, c.score))
+            .collect::<Vec<_>>()
+            .join(", ");
+        if breakdown.is_empty() {
+            "-".to_string()
+        } else {
+            breakdown
+        }
+    }
+}